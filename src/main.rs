@@ -4,107 +4,255 @@ extern crate rusttracer;
 
 use argparse::{ArgumentParser, Store, StoreTrue};
 use rusttracer::math::Vector3;
-use rusttracer::raytracing::Light;
-use rusttracer::raytracing::{Camera, Scene};
+use rusttracer::raytracing::{Light, Material};
+use rusttracer::raytracing::{Camera, FovAxis, Renderer, Scene};
 use rusttracer::shapes::Sphere;
-use rusttracer::util::image_output;
+use rusttracer::util::tonemap::{Operator, ToneMapping};
+use rusttracer::util::exr::Precision;
+use rusttracer::util::image_output::{self, ImageFormat};
 use rusttracer::util::ppm;
+use rusttracer::util::scene_file::{self, SceneDescription};
 use rusttracer::vec3;
 use std::f64;
 use std::io::{self, Write};
-const WIDTH: u16 = 512;
-const HEIGHT: u16 = 512;
+use std::path::Path;
+use std::process;
 
 fn main() {
 
-    let mut field_of_view = 75.0;
+    let mut field_of_view = 0.0;
     let mut write_file = "".to_string();
     let mut write_to_stdout = false;
+    let mut threads = 0;
+    let mut scene_path = "".to_string();
+    let mut max_depth = 5;
+    let mut samples_per_pixel = 1;
+    let mut width = 0;
+    let mut height = 0;
+    let mut vertical_fov = false;
+    let mut tone_mapping_operator = "clamp".to_string();
+    let mut exposure = 0.0;
+    let mut white_point = 0.0;
+    let mut linear_output = false;
+    let mut exr_float = false;
+    let mut ascii_ppm = false;
     {
         let mut parser = ArgumentParser::new();
         parser.set_description("A raytracer");
         parser
             .refer(&mut field_of_view)
-            .add_option(&["-f", "--fov"], Store, "The field of view.");
+            .add_option(
+                &["-f", "--fov"],
+                Store,
+                "The field of view, defaults to 75 or the field of view of the scene.",
+            );
+        parser.refer(&mut vertical_fov).add_option(
+            &["--vertical-fov"],
+            StoreTrue,
+            "Apply the field of view to the image height instead of its width.",
+        );
+        parser.refer(&mut width).add_option(
+            &["--width"],
+            Store,
+            "The image width, defaults to 512 or the scene resolution.",
+        );
+        parser.refer(&mut height).add_option(
+            &["--height"],
+            Store,
+            "The image height, defaults to 512 or the scene resolution.",
+        );
         parser.refer(&mut write_file).add_option(
             &["-w", "--write-file"],
             Store,
-            "Write output to a file.",
+            "Write output to a file, the format is chosen by the extension: png, ppm, or the \
+             unclamped hdr, pfm and exr.",
         );
         parser.refer(&mut write_to_stdout).add_option(
             &["-s", "--write-stdout"],
             StoreTrue,
             "Write output to stdout.",
         );
+        parser.refer(&mut threads).add_option(
+            &["-t", "--threads"],
+            Store,
+            "The number of render threads, defaults to all cores.",
+        );
+        parser.refer(&mut scene_path).add_option(
+            &["--scene"],
+            Store,
+            "Render the scene described in this JSON file.",
+        );
+        parser.refer(&mut max_depth).add_option(
+            &["--max-depth"],
+            Store,
+            "The maximum number of reflections and refractions of a ray.",
+        );
+        parser.refer(&mut samples_per_pixel).add_option(
+            &["--spp"],
+            Store,
+            "The number of anti-aliasing samples per pixel.",
+        );
+        parser.refer(&mut tone_mapping_operator).add_option(
+            &["--tonemap"],
+            Store,
+            "The tone mapping operator, one of clamp, reinhard, extended-reinhard or aces.",
+        );
+        parser.refer(&mut exposure).add_option(
+            &["--exposure"],
+            Store,
+            "Scale the brightness by 2^EXPOSURE before tone mapping.",
+        );
+        parser.refer(&mut white_point).add_option(
+            &["--white-point"],
+            Store,
+            "The radiance mapped to white by extended-reinhard, defaults to the brightest pixel.",
+        );
+        parser.refer(&mut linear_output).add_option(
+            &["--linear"],
+            StoreTrue,
+            "Write linear values instead of applying the sRGB transfer function.",
+        );
+        parser.refer(&mut exr_float).add_option(
+            &["--exr-float"],
+            StoreTrue,
+            "Write 32 bit floats to EXR files instead of 16 bit halves.",
+        );
+        parser.refer(&mut ascii_ppm).add_option(
+            &["--ascii"],
+            StoreTrue,
+            "Write PPM files and stdout as plain text P3 instead of binary P6.",
+        );
 
         parser.parse_args_or_exit();
     }
 
-    let ppm = raytrace(field_of_view);
+    let operator = match tone_mapping_operator.parse() {
+        Ok(Operator::ExtendedReinhard(_)) if white_point > 0.0 => {
+            Operator::ExtendedReinhard(Some(white_point))
+        }
+        Ok(operator) => operator,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let tone_mapping = ToneMapping::new(operator, exposure, !linear_output);
+    let precision = if exr_float {
+        Precision::Float
+    } else {
+        Precision::Half
+    };
+    let format = if write_file.is_empty() {
+        None
+    } else {
+        match ImageFormat::from_path(&write_file) {
+            Ok(ImageFormat::Ppm) if ascii_ppm => Some(ImageFormat::PlainPpm),
+            Ok(format) => Some(format),
+            Err(e) => {
+                eprintln!("Could not write image {}", e);
+                process::exit(1);
+            }
+        }
+    };
 
-    if write_file != "" {
-        image_output::write_png_img(
-            &ppm.get_raw_bytes(),
-            ppm.get_width(),
-            ppm.get_height(),
-            write_file,
-        );
-        // ppm.write_file(&write_file).expect("Could not write file");
+    let mut description = if scene_path.is_empty() {
+        default_scene()
+    } else {
+        match scene_file::load_scene(Path::new(&scene_path)) {
+            Ok(description) => description,
+            Err(e) => {
+                eprintln!("Could not load scene {}", e);
+                process::exit(1);
+            }
+        }
+    };
+    if width > 0 {
+        description.width = width;
+    }
+    if height > 0 {
+        description.height = height;
+    }
+    description
+        .camera
+        .set_resolution(description.width as f64, description.height as f64);
+    if field_of_view > 0.0 {
+        description.camera.set_fov(field_of_view);
+    }
+    if vertical_fov {
+        description.camera.fov_axis = FovAxis::Vertical;
+    }
+    description.scene.max_depth = max_depth;
+    description.camera.samples_per_pixel = samples_per_pixel;
+    description.scene.build_bvh();
+
+    let framebuffer = Renderer::new(threads).render(
+        &description.scene,
+        &description.camera,
+        description.width,
+        description.height,
+    );
+
+    if let Some(format) = format {
+        if let Err(e) =
+            image_output::write_image(&framebuffer, format, &tone_mapping, precision, &write_file)
+        {
+            eprintln!("Could not write image {}", e);
+            process::exit(1);
+        }
     }
 
     if write_to_stdout {
-        if let Err(e) = io::stdout().write(&ppm.get_bytes()) {
-            println!("Could not write to stdout {}", e);
+        let image = tone_mapping.apply(&framebuffer);
+        let bytes = if ascii_ppm {
+            ppm::get_ascii_bytes(&image)
+        } else {
+            ppm::get_bytes(&image)
+        };
+        if let Err(e) = io::stdout().write_all(&bytes) {
+            eprintln!("Could not write to stdout {}", e);
+            process::exit(1);
         }
     }
 }
 
 fn add_spheres(scene: &mut Scene) {
-    scene.add_sphere(Sphere::new(
+    scene.add_shape(Sphere::new(
         vec3!(0, 0, 5),
         1.5,
-        Vector3::red(),
+        Material::new(Vector3::red()),
     ));
-    scene.add_sphere(Sphere::new(
+    scene.add_shape(Sphere::new(
         vec3!(-2.5, -2, 8),
         1.0,
-        Vector3::purple(),
+        Material::new(Vector3::purple()),
     ));
 
-    scene.add_sphere(Sphere::new(
+    scene.add_shape(Sphere::new(
         vec3!(2, 2, 5),
         1.0,
-        Vector3::orange(),
+        Material::new(Vector3::orange()),
     ));
 
-    scene.add_sphere(Sphere::new(
+    scene.add_shape(Sphere::new(
         vec3!(-3.5, -5, 5),
         0.8,
-        Vector3::green(),
+        Material::new(Vector3::green()),
     ));
 }
 
-fn raytrace(fov: f64) -> ppm::PPM {
+fn default_scene() -> SceneDescription {
     let lights = vec![
-        Light::new(1.2, vec3!(0, -5, 4)),
-        Light::new(1.9, vec3!(-5, 0, 4)),
-        Light::new(1.5, vec3!(5, 0, 4)),
+        Light::new(0.12, vec3!(0, -5, 4)),
+        Light::new(0.19, vec3!(-5, 0, 4)),
+        Light::new(0.15, vec3!(5, 0, 4)),
     ];
     let mut scene = Scene::new(lights, 0.1);
     add_spheres(&mut scene);
-    let camera = Camera::new(
-        vec3!(0, 0, -5),
-        WIDTH as f64,
-        HEIGHT as f64,
-        fov,
-    );
-    let mut ppm_img = ppm::PPM::new(WIDTH as u32, HEIGHT as u32);
-
-    for x in 0..WIDTH {
-        for y in 0..HEIGHT {
-            let rgb = camera.get_pixel_color(&scene, x, y);
-            ppm_img.set_pixel(x as u32, y as u32, rgb);
-        }
+    let camera = Camera::new(vec3!(0, 0, -5), 512.0, 512.0, 75.0);
+    SceneDescription {
+        scene,
+        camera,
+        width: 512,
+        height: 512,
     }
-    ppm_img
 }
@@ -0,0 +1,171 @@
+use math::Vector3;
+use raytracing::Ray;
+use std::f64;
+
+// An axis-aligned bounding box given by its minimum and maximum corner
+#[derive(Debug, Clone, PartialEq)]
+pub struct AABB {
+    pub min: Vector3,
+    pub max: Vector3,
+}
+
+impl AABB {
+    pub fn new(min: Vector3, max: Vector3) -> AABB {
+        AABB { min, max }
+    }
+
+    pub fn empty() -> AABB {
+        // The identity for union, it contains nothing
+        AABB {
+            min: Vector3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Vector3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn from_point(p: &Vector3) -> AABB {
+        AABB::new(p.clone(), p.clone())
+    }
+
+    pub fn union(&self, other: &AABB) -> AABB {
+        // Returns the smallest box containing both self and other
+        AABB {
+            min: Vector3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Vector3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.min.x.is_finite()
+            && self.min.y.is_finite()
+            && self.min.z.is_finite()
+            && self.max.x.is_finite()
+            && self.max.y.is_finite()
+            && self.max.z.is_finite()
+    }
+
+    pub fn centroid(&self) -> Vector3 {
+        &(&self.min + &self.max) * 0.5
+    }
+
+    pub fn extent(&self) -> Vector3 {
+        &self.max - &self.min
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let d = self.extent();
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    pub fn largest_axis(&self) -> usize {
+        let d = self.extent();
+        if d.x > d.y && d.x > d.z {
+            0
+        } else if d.y > d.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    // Slab test of the ray against the box. The inverse of the ray's direction is passed in
+    // since it is shared by all boxes tested against the same ray.
+    // Returns the ray parameter at which the ray enters the box, if it does so before t_max.
+    pub fn intersect(&self, ray: &Ray, inverse_direction: &Vector3, t_max: f64) -> Option<f64> {
+        let mut t_enter = 0.0;
+        let mut t_exit = t_max;
+        for axis in 0..3 {
+            let origin = ray.origin.component(axis);
+            let inverse = inverse_direction.component(axis);
+            let mut t_near = (self.min.component(axis) - origin) * inverse;
+            let mut t_far = (self.max.component(axis) - origin) * inverse;
+            if t_near > t_far {
+                ::std::mem::swap(&mut t_near, &mut t_far);
+            }
+            // Guard against rounding errors making us miss a box we just graze
+            t_far *= 1.0 + 1e-9;
+            // max and min ignore NaN, which occurs for an origin on a slab of a flat box
+            t_enter = t_near.max(t_enter);
+            t_exit = t_far.min(t_exit);
+            if t_enter > t_exit {
+                return None;
+            }
+        }
+        Some(t_enter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> AABB {
+        AABB::new(Vector3::new(-1.0, -1.0, -1.0), Vector3::new(1.0, 1.0, 1.0))
+    }
+
+    fn inverse(direction: &Vector3) -> Vector3 {
+        Vector3::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z)
+    }
+
+    #[test]
+    fn test_union_contains_both_boxes() {
+        let a = AABB::new(Vector3::new(-1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 1.0));
+        let b = AABB::new(Vector3::new(0.0, -2.0, 0.5), Vector3::new(3.0, 0.0, 2.0));
+        let union = a.union(&b);
+        assert_eq!(union.min, Vector3::new(-1.0, -2.0, 0.0));
+        assert_eq!(union.max, Vector3::new(3.0, 1.0, 2.0));
+        assert_eq!(AABB::empty().union(&a), a);
+    }
+
+    #[test]
+    fn test_surface_area() {
+        assert_eq!(unit_box().surface_area(), 24.0);
+        assert_eq!(AABB::empty().surface_area(), 0.0);
+    }
+
+    #[test]
+    fn test_ray_box_intersection() {
+        let ray = Ray::new(Vector3::new(0.5, 0.5, -5.0), Vector3::new(0.0, 0.0, 1.0));
+        let inverse_direction = inverse(&ray.direction);
+        assert_eq!(
+            unit_box().intersect(&ray, &inverse_direction, f64::MAX),
+            Some(4.0)
+        );
+        // The box lies beyond the maximum distance
+        assert_eq!(unit_box().intersect(&ray, &inverse_direction, 3.0), None);
+    }
+
+    #[test]
+    fn test_ray_box_intersection_returns_none_on_miss() {
+        let ray = Ray::new(Vector3::new(2.0, 0.0, -5.0), Vector3::new(0.0, 0.0, 1.0));
+        let inverse_direction = inverse(&ray.direction);
+        assert_eq!(
+            unit_box().intersect(&ray, &inverse_direction, f64::MAX),
+            None
+        );
+    }
+
+    #[test]
+    fn test_ray_inside_box_enters_at_zero() {
+        let ray = Ray::new(Vector3::zero(), Vector3::new(1.0, 1.0, 0.0));
+        let inverse_direction = inverse(&ray.direction);
+        assert_eq!(
+            unit_box().intersect(&ray, &inverse_direction, f64::MAX),
+            Some(0.0)
+        );
+    }
+}
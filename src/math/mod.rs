@@ -1,3 +1,7 @@
+pub mod aabb;
+pub mod random;
 pub mod vector3;
 
-pub use self::vector3::Vector3;
\ No newline at end of file
+pub use self::aabb::AABB;
+pub use self::random::{stratified_samples, Rng};
+pub use self::vector3::Vector3;
@@ -0,0 +1,101 @@
+// A small xorshift64* pseudo random number generator.
+// We want reproducible sequences for a given seed, so that renders are deterministic.
+// From https://en.wikipedia.org/wiki/Xorshift#xorshift*
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // The state must never be zero, so we scramble the seed with a splitmix64 step
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Rng {
+            state: if z == 0 { 1 } else { z },
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // Returns a uniformly distributed number in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        // Use the upper 53 bits, which is the precision of an f64 mantissa
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Returns a uniformly distributed number in [min, max)
+    pub fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.next_f64()
+    }
+}
+
+// Returns count points in [0, 1) x [0, 1) that cover the square evenly. A single point lies in
+// the center, otherwise the square is split into a grid of strata with one jittered point
+// each and whatever does not fit into the grid is placed at random.
+pub fn stratified_samples(count: u32, rng: &mut Rng) -> Vec<(f64, f64)> {
+    if count == 1 {
+        return vec![(0.5, 0.5)];
+    }
+    let strata = (count as f64).sqrt() as u32;
+    let cell = 1.0 / strata as f64;
+    let mut samples = Vec::with_capacity(count as usize);
+    for sy in 0..strata {
+        for sx in 0..strata {
+            samples.push((
+                (sx as f64 + rng.next_f64()) * cell,
+                (sy as f64 + rng.next_f64()) * cell,
+            ));
+        }
+    }
+    while samples.len() < count as usize {
+        samples.push((rng.next_f64(), rng.next_f64()));
+    }
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_gives_same_sequence() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn test_next_f64_is_in_unit_interval() {
+        let mut rng = Rng::new(0);
+        for _ in 0..10000 {
+            let x = rng.next_f64();
+            assert!((0.0..1.0).contains(&x));
+        }
+    }
+
+    #[test]
+    fn test_stratified_samples() {
+        let mut rng = Rng::new(7);
+        assert_eq!(stratified_samples(1, &mut rng), vec![(0.5, 0.5)]);
+
+        // Every cell of the 4x4 grid contains exactly one of the first 16 samples
+        let samples = stratified_samples(18, &mut rng);
+        assert_eq!(samples.len(), 18);
+        let mut cells = [0; 16];
+        for &(x, y) in &samples[..16] {
+            assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+            cells[(y * 4.0) as usize * 4 + (x * 4.0) as usize] += 1;
+        }
+        assert!(cells.iter().all(|&count| count == 1));
+    }
+}
@@ -63,6 +63,15 @@ impl Vector3 {
         (self.x.powi(2) + self.y.powi(2) + self.z.powi(2)).sqrt()
     }
 
+    pub fn component(&self, axis: usize) -> f64 {
+        // Returns x, y or z for axis 0, 1 or 2
+        match axis {
+            0 => self.x,
+            1 => self.y,
+            _ => self.z,
+        }
+    }
+
     pub fn inverse(&self) -> Vector3 {
         Vector3::new(-self.x, -self.y, -self.z)
     }
@@ -71,6 +80,43 @@ impl Vector3 {
         // Reflects self at other
         self - &(&(other * (self % other)) * 2.0)
     }
+
+    pub fn refract(&self, normal: &Vector3, eta: f64) -> Option<Vector3> {
+        // Refracts self, a normalized direction, at a surface with the given normal using
+        // Snell's law. The normal has to point against self and eta is the ratio n1 / n2 of
+        // the refractive indices of the medium we come from and the one we enter.
+        // Returns None in case of total internal reflection.
+        // From https://www.scratchapixel.com/lessons/3d-basic-rendering/introduction-to-shading/reflection-refraction-fresnel
+        let cos_incident = -(self % normal);
+        let sin2_transmitted = eta * eta * (1.0 - cos_incident * cos_incident);
+        if sin2_transmitted > 1.0 {
+            return None;
+        }
+        let cos_transmitted = (1.0 - sin2_transmitted).sqrt();
+        Some(&(self * eta) + &(normal * (eta * cos_incident - cos_transmitted)))
+    }
+
+    pub fn cross(&self, other: &Vector3) -> Vector3 {
+        Vector3 {
+            x: self.y * other.z - self.z * other.y,
+            y: self.z * other.x - self.x * other.z,
+            z: self.x * other.y - self.y * other.x,
+        }
+    }
+
+    pub fn orthonormal_basis(&self) -> (Vector3, Vector3) {
+        // Returns two normalized vectors that are orthogonal to self and to each other.
+        // Self is expected to be normalized. We cross with the coordinate axis that is
+        // least aligned with self to avoid a degenerate cross product.
+        let helper = if self.x.abs() < 0.9 {
+            Vector3::new(1.0, 0.0, 0.0)
+        } else {
+            Vector3::new(0.0, 1.0, 0.0)
+        };
+        let tangent = self.cross(&helper).normalize();
+        let bitangent = self.cross(&tangent);
+        (tangent, bitangent)
+    }
 }
 
 impl Clone for Vector3 {
@@ -83,7 +129,7 @@ impl Clone for Vector3 {
     }
 }
 
-impl<'a, 'b> Rem<&'b Vector3> for &'a Vector3 {
+impl<'b> Rem<&'b Vector3> for &Vector3 {
     type Output = f64;
 
     fn rem(self, vec: &'b Vector3) -> f64 {
@@ -91,7 +137,7 @@ impl<'a, 'b> Rem<&'b Vector3> for &'a Vector3 {
     }
 }
 
-impl<'a, 'b> Sub<&'b Vector3> for &'a Vector3 {
+impl<'b> Sub<&'b Vector3> for &Vector3 {
     type Output = Vector3;
 
     fn sub(self, vec: &'b Vector3) -> Vector3 {
@@ -103,7 +149,7 @@ impl<'a, 'b> Sub<&'b Vector3> for &'a Vector3 {
     }
 }
 
-impl<'a, 'b> Add<&'b Vector3> for &'a Vector3 {
+impl<'b> Add<&'b Vector3> for &Vector3 {
     type Output = Vector3;
 
     fn add(self, vec: &'b Vector3) -> Vector3 {
@@ -115,7 +161,7 @@ impl<'a, 'b> Add<&'b Vector3> for &'a Vector3 {
     }
 }
 
-impl<'a, 'b> Mul<&'b Vector3> for &'a Vector3 {
+impl<'b> Mul<&'b Vector3> for &Vector3 {
     type Output = Vector3;
 
     fn mul(self, vec: &'b Vector3) -> Vector3 {
@@ -127,7 +173,7 @@ impl<'a, 'b> Mul<&'b Vector3> for &'a Vector3 {
     }
 }
 
-impl<'a> Mul<f64> for &'a Vector3 {
+impl Mul<f64> for &Vector3 {
     type Output = Vector3;
 
     fn mul(self, t: f64) -> Vector3 {
@@ -139,7 +185,7 @@ impl<'a> Mul<f64> for &'a Vector3 {
     }
 }
 
-impl<'a> Div<f64> for &'a Vector3 {
+impl Div<f64> for &Vector3 {
     type Output = Vector3;
 
     fn div(self, di: f64) -> Vector3 {
@@ -189,4 +235,57 @@ mod test {
         let normal = Vector3::new(0.0, 1.0, 0.0);
         assert_eq!(vec.reflect(&normal), expected_reflection);
     }
+
+    #[test]
+    fn test_refraction_at_normal_incidence_goes_straight() {
+        let direction = Vector3::new(0.0, -1.0, 0.0);
+        let normal = Vector3::new(0.0, 1.0, 0.0);
+        assert_eq!(direction.refract(&normal, 1.0 / 1.5), Some(direction));
+    }
+
+    #[test]
+    fn test_refraction_follows_snells_law() {
+        // Entering glass at 45 degrees, sin(t) = sin(45) / 1.5
+        let direction = Vector3::new(1.0, -1.0, 0.0).normalize();
+        let normal = Vector3::new(0.0, 1.0, 0.0);
+        let refracted = direction.refract(&normal, 1.0 / 1.5).unwrap();
+        let expected_sin = (std::f64::consts::PI / 4.0).sin() / 1.5;
+        assert!((refracted.len() - 1.0).abs() < 1e-14);
+        assert!((refracted.x - expected_sin).abs() < 1e-14);
+        assert!(refracted.y < 0.0);
+    }
+
+    #[test]
+    fn test_total_internal_reflection() {
+        // Leaving glass at 60 degrees is beyond the critical angle of asin(1 / 1.5) = 41.8
+        let angle = 60.0_f64.to_radians();
+        let direction = Vector3::new(angle.sin(), angle.cos(), 0.0);
+        let normal = Vector3::new(0.0, -1.0, 0.0);
+        assert_eq!(direction.refract(&normal, 1.5), None);
+
+        // Just below the critical angle light still leaves the glass, almost grazing
+        let angle = 41.0_f64.to_radians();
+        let direction = Vector3::new(angle.sin(), angle.cos(), 0.0);
+        let refracted = direction.refract(&normal, 1.5).unwrap();
+        assert!(refracted.x > 0.98);
+    }
+
+    #[test]
+    fn test_cross_product() {
+        let x = Vector3::new(1.0, 0.0, 0.0);
+        let y = Vector3::new(0.0, 1.0, 0.0);
+        assert_eq!(x.cross(&y), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(y.cross(&x), Vector3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn test_orthonormal_basis() {
+        let normal = Vector3::new(1.0, 2.0, -3.0).normalize();
+        let (tangent, bitangent) = normal.orthonormal_basis();
+        assert!((&normal % &tangent).abs() < 1e-14);
+        assert!((&normal % &bitangent).abs() < 1e-14);
+        assert!((&tangent % &bitangent).abs() < 1e-14);
+        assert!((tangent.len() - 1.0).abs() < 1e-14);
+        assert!((bitangent.len() - 1.0).abs() < 1e-14);
+    }
 }
@@ -0,0 +1,78 @@
+use math::Vector3;
+use raytracing::{EnvironmentMap, LightSource};
+
+// What rays see that don't hit anything. Colors are given in [0, 255].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Background {
+    Color(Vector3),
+    // Blends from the bottom color straight below to the top color straight above
+    Gradient { bottom: Vector3, top: Vector3 },
+    // An image of the surroundings, which also lights the scene
+    Environment(EnvironmentMap),
+}
+
+impl Background {
+    pub fn color(&self, direction: &Vector3) -> Vector3 {
+        match self {
+            Background::Color(color) => color.clone(),
+            Background::Gradient { bottom, top } => {
+                let t = (direction.normalize().y + 1.0) / 2.0;
+                &(bottom * (1.0 - t)) + &(top * t)
+            }
+            // A radiance of 1.0 is white
+            Background::Environment(map) => &map.radiance(direction) * 255.0,
+        }
+    }
+
+    // Returns the background as a light, which only environment maps are
+    pub fn light(&self) -> Option<&dyn LightSource> {
+        match self {
+            Background::Environment(map) => Some(map),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Background {
+    fn default() -> Background {
+        Background::Color(Vector3::new(127.0, 127.0, 127.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::framebuffer::{Framebuffer, LinearRGB};
+
+    #[test]
+    fn test_gradient() {
+        let background = Background::Gradient {
+            bottom: Vector3::zero(),
+            top: Vector3::new(0.0, 100.0, 200.0),
+        };
+        assert_eq!(
+            background.color(&Vector3::new(0.0, 5.0, 0.0)),
+            Vector3::new(0.0, 100.0, 200.0)
+        );
+        assert_eq!(
+            background.color(&Vector3::new(3.0, 0.0, 4.0)),
+            Vector3::new(0.0, 50.0, 100.0)
+        );
+        assert_eq!(
+            background.color(&Vector3::new(0.0, -1.0, 0.0)),
+            Vector3::zero()
+        );
+        assert!(background.light().is_none());
+    }
+
+    #[test]
+    fn test_environment_is_seen_and_lights_the_scene() {
+        let image = Framebuffer::filled(8, 4, LinearRGB::new(0.5, 1.0, 2.0));
+        let background = Background::Environment(EnvironmentMap::new(image, 0.0));
+        assert_eq!(
+            background.color(&Vector3::new(1.0, 0.0, 0.0)),
+            Vector3::new(127.5, 255.0, 510.0)
+        );
+        assert!(background.light().is_some());
+    }
+}
@@ -0,0 +1,285 @@
+use math::{Vector3, AABB};
+use raytracing::Ray;
+use shapes::Shape;
+use std::f64;
+
+// A bounding volume hierarchy over the shapes of a scene.
+// The tree is built with the surface area heuristic and stored as a flat array in depth first
+// order, so the first child of an interior node directly follows it.
+// See http://www.pbr-book.org/3ed-2018/Primitives_and_Intersection_Acceleration/Bounding_Volume_Hierarchies.html
+
+// Number of buckets the centroids are binned into when evaluating split candidates
+const SAH_BUCKETS: usize = 12;
+// Cost of traversing an interior node relative to intersecting a shape
+const TRAVERSAL_COST: f64 = 0.125;
+// Leaves never get larger than this, even if the SAH would prefer it
+const MAX_SHAPES_IN_LEAF: usize = 8;
+
+#[derive(Debug)]
+struct BvhNode {
+    bounds: AABB,
+    // For leaves the index of the first shape in `shape_ids`,
+    // for interior nodes the index of the second child
+    offset: usize,
+    // Number of shapes in a leaf, zero for interior nodes
+    count: usize,
+    // The axis an interior node was split along
+    axis: usize,
+}
+
+struct BuildShape {
+    id: usize,
+    bounds: AABB,
+    centroid: Vector3,
+}
+
+#[derive(Debug)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    shape_ids: Vec<usize>,
+    // Shapes without finite bounds, like planes, cannot be put into the tree and are
+    // tested on their own
+    unbounded: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn build(shapes: &[Box<dyn Shape>]) -> Bvh {
+        let mut build_shapes = Vec::with_capacity(shapes.len());
+        let mut unbounded = Vec::new();
+        for (id, shape) in shapes.iter().enumerate() {
+            let bounds = shape.bounding_box();
+            if bounds.is_finite() {
+                build_shapes.push(BuildShape {
+                    id,
+                    centroid: bounds.centroid(),
+                    bounds,
+                });
+            } else {
+                unbounded.push(id);
+            }
+        }
+
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * build_shapes.len()),
+            shape_ids: Vec::with_capacity(build_shapes.len()),
+            unbounded,
+        };
+        if !build_shapes.is_empty() {
+            bvh.build_recursive(&mut build_shapes);
+        }
+        bvh
+    }
+
+    fn build_recursive(&mut self, shapes: &mut [BuildShape]) -> usize {
+        let bounds = shapes
+            .iter()
+            .fold(AABB::empty(), |bounds, shape| bounds.union(&shape.bounds));
+        let centroid_bounds = shapes.iter().fold(AABB::empty(), |bounds, shape| {
+            bounds.union(&AABB::from_point(&shape.centroid))
+        });
+        let axis = centroid_bounds.largest_axis();
+        let axis_min = centroid_bounds.min.component(axis);
+        let axis_extent = centroid_bounds.max.component(axis) - axis_min;
+
+        let node_index = self.nodes.len();
+        // All centroids in the same spot cannot be split any further
+        if shapes.len() == 1 || axis_extent <= 0.0 {
+            self.push_leaf(bounds, shapes);
+            return node_index;
+        }
+
+        let bucket_of = |shape: &BuildShape| {
+            let relative = (shape.centroid.component(axis) - axis_min) / axis_extent;
+            ((relative * SAH_BUCKETS as f64) as usize).min(SAH_BUCKETS - 1)
+        };
+
+        let mut bucket_counts = [0usize; SAH_BUCKETS];
+        let mut bucket_bounds: Vec<AABB> = (0..SAH_BUCKETS).map(|_| AABB::empty()).collect();
+        for shape in shapes.iter() {
+            let bucket = bucket_of(shape);
+            bucket_counts[bucket] += 1;
+            bucket_bounds[bucket] = bucket_bounds[bucket].union(&shape.bounds);
+        }
+
+        // Evaluate the cost of splitting after every bucket
+        let mut best_split = 0;
+        let mut best_cost = f64::INFINITY;
+        for split in 0..SAH_BUCKETS - 1 {
+            let (mut left_bounds, mut left_count) = (AABB::empty(), 0);
+            let (mut right_bounds, mut right_count) = (AABB::empty(), 0);
+            for bucket in 0..SAH_BUCKETS {
+                if bucket <= split {
+                    left_bounds = left_bounds.union(&bucket_bounds[bucket]);
+                    left_count += bucket_counts[bucket];
+                } else {
+                    right_bounds = right_bounds.union(&bucket_bounds[bucket]);
+                    right_count += bucket_counts[bucket];
+                }
+            }
+            let cost = TRAVERSAL_COST
+                + (left_count as f64 * left_bounds.surface_area()
+                    + right_count as f64 * right_bounds.surface_area())
+                    / bounds.surface_area().max(f64::MIN_POSITIVE);
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = split;
+            }
+        }
+
+        // Intersecting all shapes of a leaf costs one unit per shape
+        if shapes.len() <= MAX_SHAPES_IN_LEAF && best_cost >= shapes.len() as f64 {
+            self.push_leaf(bounds, shapes);
+            return node_index;
+        }
+
+        let mut middle = partition(shapes, |shape| bucket_of(shape) <= best_split);
+        if middle == 0 || middle == shapes.len() {
+            // Can only happen through rounding, fall back to splitting in the middle
+            middle = shapes.len() / 2;
+        }
+
+        self.nodes.push(BvhNode {
+            bounds,
+            offset: 0,
+            count: 0,
+            axis,
+        });
+        let (left, right) = shapes.split_at_mut(middle);
+        self.build_recursive(left);
+        let second_child = self.build_recursive(right);
+        self.nodes[node_index].offset = second_child;
+        node_index
+    }
+
+    fn push_leaf(&mut self, bounds: AABB, shapes: &[BuildShape]) {
+        self.nodes.push(BvhNode {
+            bounds,
+            offset: self.shape_ids.len(),
+            count: shapes.len(),
+            axis: 0,
+        });
+        self.shape_ids.extend(shapes.iter().map(|shape| shape.id));
+    }
+
+    // Returns the id and ray parameter of the closest shape hit by the ray
+    pub fn closest_hit(&self, ray: &Ray, shapes: &[Box<dyn Shape>]) -> Option<(usize, f64)> {
+        let mut closest: Option<(usize, f64)> = None;
+        let mut t_closest = f64::MAX;
+
+        for &id in &self.unbounded {
+            if let Some(t) = shapes[id].intersect(ray) {
+                if t < t_closest {
+                    t_closest = t;
+                    closest = Some((id, t));
+                }
+            }
+        }
+
+        if self.nodes.is_empty() {
+            return closest;
+        }
+
+        let inverse_direction = inverse_direction(ray);
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0);
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            // Nodes further away than the closest hit so far cannot contain a closer one
+            if node
+                .bounds
+                .intersect(ray, &inverse_direction, t_closest)
+                .is_none()
+            {
+                continue;
+            }
+
+            if node.count > 0 {
+                for &id in &self.shape_ids[node.offset..node.offset + node.count] {
+                    if let Some(t) = shapes[id].intersect(ray) {
+                        if t < t_closest {
+                            t_closest = t;
+                            closest = Some((id, t));
+                        }
+                    }
+                }
+            } else {
+                push_children(&mut stack, ray, node, index);
+            }
+        }
+
+        closest
+    }
+
+    // Returns whether the ray hits any shape with a ray parameter smaller than t_max.
+    // Stops at the first hit, regardless of whether it is the closest one.
+    pub fn any_hit(&self, ray: &Ray, shapes: &[Box<dyn Shape>], t_max: f64) -> bool {
+        let hits = |id: usize| shapes[id].intersect(ray).is_some_and(|t| t < t_max);
+
+        if self.unbounded.iter().any(|&id| hits(id)) {
+            return true;
+        }
+
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let inverse_direction = inverse_direction(ray);
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0);
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node
+                .bounds
+                .intersect(ray, &inverse_direction, t_max)
+                .is_none()
+            {
+                continue;
+            }
+
+            if node.count > 0 {
+                let leaf = &self.shape_ids[node.offset..node.offset + node.count];
+                if leaf.iter().any(|&id| hits(id)) {
+                    return true;
+                }
+            } else {
+                push_children(&mut stack, ray, node, index);
+            }
+        }
+
+        false
+    }
+}
+
+fn inverse_direction(ray: &Ray) -> Vector3 {
+    Vector3::new(
+        1.0 / ray.direction.x,
+        1.0 / ray.direction.y,
+        1.0 / ray.direction.z,
+    )
+}
+
+fn push_children(stack: &mut Vec<usize>, ray: &Ray, node: &BvhNode, index: usize) {
+    // Visit the child closer to the ray's origin first, so that we find close hits early
+    // and can skip the far child. The stack is last in first out, so it is pushed last.
+    let first_child = index + 1;
+    if ray.direction.component(node.axis) < 0.0 {
+        stack.push(first_child);
+        stack.push(node.offset);
+    } else {
+        stack.push(node.offset);
+        stack.push(first_child);
+    }
+}
+
+// Reorders the slice so that all elements satisfying the predicate come first and returns
+// the number of those elements
+fn partition<T, F: Fn(&T) -> bool>(items: &mut [T], predicate: F) -> usize {
+    let mut middle = 0;
+    for i in 0..items.len() {
+        if predicate(&items[i]) {
+            items.swap(i, middle);
+            middle += 1;
+        }
+    }
+    middle
+}
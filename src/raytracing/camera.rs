@@ -1,12 +1,27 @@
-use math::Vector3;
+use math::{stratified_samples, Rng, Vector3};
 use raytracing::{Ray, Scene};
-use util::ppm;
+use util::framebuffer::LinearRGB;
+
+// Which extent of the image the field of view spans, the other one follows from the
+// aspect ratio
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FovAxis {
+    Horizontal,
+    Vertical,
+}
 
 pub struct Camera {
     origin: Vector3,
+    // Orthonormal basis of the camera, the plane is spanned by right and up and lies one
+    // unit along forward
+    right: Vector3,
+    up: Vector3,
+    forward: Vector3,
     width: f64,
     height: f64,
     fov: f64,
+    pub fov_axis: FovAxis,
+    pub samples_per_pixel: u32,
 }
 
 impl Camera {
@@ -14,9 +29,14 @@ impl Camera {
         // Constructs a new Camera with the given width and height and a fov given in degrees
         Camera {
             origin: Vector3::zero(),
+            right: Vector3::new(1.0, 0.0, 0.0),
+            up: Vector3::new(0.0, 1.0, 0.0),
+            forward: Vector3::new(0.0, 0.0, 1.0),
             width,
             height,
             fov: (fov / 2.0).to_radians(),
+            fov_axis: FovAxis::Horizontal,
+            samples_per_pixel: 1,
         }
     }
 
@@ -24,63 +44,294 @@ impl Camera {
         // Constructs a new Camera with the given position, width, height and a fov given in degrees
         Camera {
             origin: position,
+            right: Vector3::new(1.0, 0.0, 0.0),
+            up: Vector3::new(0.0, 1.0, 0.0),
+            forward: Vector3::new(0.0, 0.0, 1.0),
+            width,
+            height,
+            fov: (fov / 2.0).to_radians(),
+            fov_axis: FovAxis::Horizontal,
+            samples_per_pixel: 1,
+        }
+    }
+
+    pub fn look_at(
+        eye: Vector3,
+        target: &Vector3,
+        up: &Vector3,
+        width: f64,
+        height: f64,
+        fov: f64,
+    ) -> Camera {
+        // Constructs a new Camera at eye looking towards target, oriented so that up points
+        // upwards in the image. If up is parallel to the view direction any orthogonal
+        // vector is used instead.
+        let forward = (target - &eye).normalize();
+        let right = up.cross(&forward);
+        let right = if right.len() < 1e-9 {
+            forward.orthonormal_basis().0
+        } else {
+            right.normalize()
+        };
+        let up = forward.cross(&right);
+        Camera {
+            origin: eye,
+            right,
+            up,
+            forward,
             width,
             height,
             fov: (fov / 2.0).to_radians(),
+            fov_axis: FovAxis::Horizontal,
+            samples_per_pixel: 1,
         }
     }
 
-    pub fn map_pixel_to_plane(&self, x: u16, y: u16) -> Vector3 {
-        // For a given pixel pair, returns the position on the perspective camera plane
-        let x_coord = (2.0 * x as f64 - self.width) / self.width * (self.fov).tan();
-        let y_coord = (2.0 * y as f64 - self.height) / self.height
-            * (self.height / self.width * self.fov).tan();
+    pub fn roll(&mut self, degrees: f64) {
+        // Rotates the camera around its view direction by the given angle in degrees,
+        // turning right towards up
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let right = &(&self.right * cos) + &(&self.up * sin);
+        let up = &(&self.up * cos) - &(&self.right * sin);
+        self.right = right;
+        self.up = up;
+    }
+
+    pub fn set_resolution(&mut self, width: f64, height: f64) {
+        self.width = width;
+        self.height = height;
+    }
+
+    pub fn set_fov(&mut self, fov: f64) {
+        // The fov is given in degrees, like in the constructors
+        self.fov = (fov / 2.0).to_radians();
+    }
+
+    fn plane_extent(&self) -> (f64, f64) {
+        // Returns the half width and half height of the camera plane at distance one
+        let tan = self.fov.tan();
+        match self.fov_axis {
+            FovAxis::Horizontal => (tan, tan * self.height / self.width),
+            FovAxis::Vertical => (tan * self.width / self.height, tan),
+        }
+    }
+
+    pub fn map_pixel_to_plane(&self, x: f64, y: f64) -> Vector3 {
+        // For a given position in pixel coordinates, returns the position on the perspective
+        // camera plane. Fractional coordinates address points inside a pixel. Like images the
+        // pixel coordinates start in the top left corner, so y = 0 maps to the top of the plane.
+        let (half_width, half_height) = self.plane_extent();
+        let x_coord = (2.0 * x - self.width) / self.width * half_width;
+        let y_coord = (self.height - 2.0 * y) / self.height * half_height;
         Vector3::new(x_coord, y_coord, 1.0)
     }
 
-    pub fn get_camera_ray(&self, x: u16, y: u16) -> Ray {
+    pub fn get_camera_ray(&self, x: f64, y: f64) -> Ray {
         // Gets the camera ray going from the camera origin through pixel x, y
-        // on the camera plane
+        // on the camera plane, transformed from camera into world space
         let plane_position = self.map_pixel_to_plane(x, y);
-        Ray::new(self.origin.clone(), plane_position)
+        let direction = &(&(&self.right * plane_position.x) + &(&self.up * plane_position.y))
+            + &(&self.forward * plane_position.z);
+        Ray::new(self.origin.clone(), direction)
     }
 
-    pub fn get_pixel_color(&self, scene: &Scene, x: u16, y: u16) -> ppm::RGB {
-        let mut color = Vector3::new(127.0, 127.0, 127.0);
-        let ray = self.get_camera_ray(x, y);
-        let (hit_sphere, t) = scene.trace_scene(&ray);
-
-        match hit_sphere {
-            Some(sph) => {
-                let intersection_point = ray.get_coordinates(t);
-                color = scene.compute_color(&ray, intersection_point, &sph);
-            }
-            None => (),
+    pub fn get_pixel_color(&self, scene: &Scene, x: u32, y: u32) -> LinearRGB {
+        // Averages the colors of all samples through the pixel, the colors are linear so
+        // they can be summed directly
+        let samples = self.samples_per_pixel.max(1);
+        let mut rng = Rng::new((u64::from(y) << 32) | u64::from(x));
+        let mut color = Vector3::zero();
+        for (dx, dy) in stratified_samples(samples, &mut rng) {
+            let ray = self.get_camera_ray(x as f64 + dx, y as f64 + dy);
+            color = &color + &scene.trace_color(&ray, 0, &mut rng);
         }
-        ppm::RGB::new(color.x as u8, color.y as u8, color.z as u8)
+        // Scene colors are given in [0, 255], which maps to a radiance of [0, 1]
+        let color = &color * (1.0 / (255.0 * samples as f64));
+        LinearRGB::new(color.x as f32, color.y as f32, color.z as f32)
     }
- }
+}
 
 #[test]
 fn test_camera_returns_corners() {
-    // Passing 0, 0 should return -1, -1 the lower left corner of the plane
-    // Whereas passing width, height, should return 1, 1 the upper right corner
+    // Passing 0, 0 should return -1, 1 the upper left corner of the plane
+    // Whereas passing width, height, should return 1, -1 the lower right corner
     let width = 16.0;
     let height = 16.0;
     let camera = Camera::new_at_zero(width, height, 90.0);
-    let vec2 = camera.map_pixel_to_plane(0, 0);
+    let vec2 = camera.map_pixel_to_plane(0.0, 0.0);
 
     // For a quadratic camera and a standard fov of 90
     // these tests should hold true because
     assert!(((90.0_f64 / 2.0_f64).to_radians().tan() - 1.0) < 1e-14);
     // which just says that the tan of 45 as radians is 1.
-    // We expect x and y to be -1.0
-    assert!((vec2.x + 1.0) < 1e-14);
-    assert!((vec2.y + 1.0) < 1e-14);
+    // We expect x to be -1.0 and y to be 1.0
+    assert!((vec2.x + 1.0).abs() < 1e-14);
+    assert!((vec2.y - 1.0).abs() < 1e-14);
+
+    let vec2 = camera.map_pixel_to_plane(width, height);
 
-    let vec2 = camera.map_pixel_to_plane(width as u16, height as u16);
+    // We expect x to be 1.0 and y to be -1.0
+    assert!((vec2.x - 1.0).abs() < 1e-14);
+    assert!((vec2.y + 1.0).abs() < 1e-14);
+}
 
-    // We expect x and y to be 1.0
-    assert!((vec2.x - 1.0) < 1e-14);
-    assert!((vec2.y - 1.0) < 1e-14);
+#[test]
+fn test_camera_maps_fractional_coordinates() {
+    // The center of the image lies between the two middle pixels
+    let camera = Camera::new_at_zero(16.0, 16.0, 90.0);
+    let center = camera.map_pixel_to_plane(8.0, 8.0);
+    assert!(center.x.abs() < 1e-14);
+    assert!(center.y.abs() < 1e-14);
+    let vec2 = camera.map_pixel_to_plane(0.5, 0.5);
+    assert!((vec2.x + 15.0 / 16.0).abs() < 1e-14);
+    assert!((vec2.y - 15.0 / 16.0).abs() < 1e-14);
+}
+
+#[test]
+fn test_supersampling_blends_edges() {
+    use raytracing::{Background, Material};
+    use shapes::Triangle;
+    use util::framebuffer::RGB;
+
+    // A black triangle covers the left half of the view in front of a white background.
+    // The boundary runs through the middle of pixel 1, so half of its samples hit it.
+    let mut scene = Scene::new(vec![], 0.0);
+    scene.background = Background::Color(Vector3::new(255.0, 255.0, 255.0));
+    scene.add_shape(Triangle::new(
+        Vector3::new(0.0, -100.0, 1.0),
+        Vector3::new(0.0, 100.0, 1.0),
+        Vector3::new(-100.0, 0.0, 1.0),
+        Material::new(Vector3::zero()),
+    ));
+    let mut camera = Camera::new_at_zero(3.0, 3.0, 90.0);
+    camera.samples_per_pixel = 16;
+    assert_eq!(
+        camera.get_pixel_color(&scene, 0, 1).to_rgb(),
+        RGB::new(0, 0, 0)
+    );
+    assert_eq!(
+        camera.get_pixel_color(&scene, 1, 1).to_rgb(),
+        RGB::new(128, 128, 128)
+    );
+    assert_eq!(
+        camera.get_pixel_color(&scene, 2, 1).to_rgb(),
+        RGB::new(255, 255, 255)
+    );
+}
+
+#[test]
+fn test_look_at_down_z_matches_default_camera() {
+    let camera = Camera::new(Vector3::new(1.0, 2.0, 3.0), 16.0, 16.0, 90.0);
+    let look_at = Camera::look_at(
+        Vector3::new(1.0, 2.0, 3.0),
+        &Vector3::new(1.0, 2.0, 10.0),
+        &Vector3::new(0.0, 1.0, 0.0),
+        16.0,
+        16.0,
+        90.0,
+    );
+    for &(x, y) in &[(0.0, 0.0), (3.5, 12.0), (16.0, 7.25)] {
+        let expected = camera.get_camera_ray(x, y);
+        let actual = look_at.get_camera_ray(x, y);
+        assert_eq!(actual.origin, expected.origin);
+        assert!((&actual.direction - &expected.direction).len() < 1e-14);
+    }
+}
+
+#[test]
+fn test_look_at_transforms_into_world_space() {
+    // Looking from +x towards the origin, image right is +z and image up stays +y, like
+    // the default camera turned by 90 degrees
+    let camera = Camera::look_at(
+        Vector3::new(5.0, 0.0, 0.0),
+        &Vector3::zero(),
+        &Vector3::new(0.0, 2.0, 0.0),
+        16.0,
+        16.0,
+        90.0,
+    );
+    let center = camera.get_camera_ray(8.0, 8.0).direction;
+    assert!((&center - &Vector3::new(-1.0, 0.0, 0.0)).len() < 1e-14);
+    let corner = camera.get_camera_ray(16.0, 0.0).direction;
+    assert!((&corner - &Vector3::new(-1.0, 1.0, 1.0)).len() < 1e-14);
+
+    // An up vector parallel to the view direction still gives a valid basis
+    let camera = Camera::look_at(
+        Vector3::zero(),
+        &Vector3::new(0.0, -3.0, 0.0),
+        &Vector3::new(0.0, 1.0, 0.0),
+        16.0,
+        16.0,
+        90.0,
+    );
+    assert!((camera.right.len() - 1.0).abs() < 1e-14);
+    assert!((camera.up.len() - 1.0).abs() < 1e-14);
+    assert!((&camera.right % &camera.forward).abs() < 1e-14);
+    assert!((&camera.up % &camera.forward).abs() < 1e-14);
+}
+
+#[test]
+fn test_roll_rotates_around_view_direction() {
+    let mut camera = Camera::new_at_zero(16.0, 16.0, 90.0);
+    camera.roll(90.0);
+    // After a quarter turn the right edge of the image points up
+    let right = camera.get_camera_ray(16.0, 8.0).direction;
+    assert!((&right - &Vector3::new(0.0, 1.0, 1.0)).len() < 1e-14);
+    let center = camera.get_camera_ray(8.0, 8.0).direction;
+    assert!((&center - &Vector3::new(0.0, 0.0, 1.0)).len() < 1e-14);
+}
+
+#[test]
+fn test_widescreen_aspect_ratio() {
+    // A horizontal fov of 90 spans [-1, 1] horizontally, the height follows from 16:9.
+    // The top right corner of the image is the upper right corner of the plane.
+    let mut camera = Camera::new_at_zero(1920.0, 1080.0, 90.0);
+    let corner = camera.map_pixel_to_plane(1920.0, 0.0);
+    assert!((corner.x - 1.0).abs() < 1e-14);
+    assert!((corner.y - 9.0 / 16.0).abs() < 1e-14);
+
+    // With the vertical convention it is the other way around
+    camera.fov_axis = FovAxis::Vertical;
+    let corner = camera.map_pixel_to_plane(1920.0, 0.0);
+    assert!((corner.x - 16.0 / 9.0).abs() < 1e-14);
+    assert!((corner.y - 1.0).abs() < 1e-14);
+}
+
+#[test]
+fn test_portrait_aspect_ratio() {
+    let mut camera = Camera::new_at_zero(1080.0, 1920.0, 90.0);
+    let corner = camera.map_pixel_to_plane(1080.0, 0.0);
+    assert!((corner.x - 1.0).abs() < 1e-14);
+    assert!((corner.y - 16.0 / 9.0).abs() < 1e-14);
+
+    camera.fov_axis = FovAxis::Vertical;
+    let corner = camera.map_pixel_to_plane(1080.0, 0.0);
+    assert!((corner.x - 9.0 / 16.0).abs() < 1e-14);
+    assert!((corner.y - 1.0).abs() < 1e-14);
+}
+
+#[test]
+fn test_set_fov_matches_constructor() {
+    let mut camera = Camera::new_at_zero(300.0, 200.0, 60.0);
+    camera.set_fov(90.0);
+    let expected = Camera::new_at_zero(300.0, 200.0, 90.0);
+    assert_eq!(
+        camera.map_pixel_to_plane(300.0, 0.0),
+        expected.map_pixel_to_plane(300.0, 0.0)
+    );
+}
+
+#[test]
+fn test_pixels_are_square() {
+    // Neighbouring pixels are equally far apart in both directions, so circles stay round
+    for &(width, height) in &[(1920.0, 1080.0), (1080.0, 1920.0), (300.0, 200.0)] {
+        for &axis in &[FovAxis::Horizontal, FovAxis::Vertical] {
+            let mut camera = Camera::new_at_zero(width, height, 60.0);
+            camera.fov_axis = axis;
+            let origin = camera.map_pixel_to_plane(10.0, 10.0);
+            let dx = camera.map_pixel_to_plane(11.0, 10.0).x - origin.x;
+            let dy = origin.y - camera.map_pixel_to_plane(10.0, 11.0).y;
+            assert!((dx - dy).abs() < 1e-14);
+        }
+    }
 }
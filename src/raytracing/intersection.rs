@@ -0,0 +1,12 @@
+use math::Vector3;
+
+// The hit record of a ray with an object in the scene
+#[derive(Debug)]
+pub struct Intersection {
+    pub t: f64,
+    pub point: Vector3,
+    pub normal: Vector3,
+    pub uv: (f64, f64),
+    // The index of the hit object in the scene's shapes
+    pub object_id: usize,
+}
@@ -0,0 +1,53 @@
+use math::{Rng, Vector3};
+use raytracing::light::light_source::tinted;
+use raytracing::{LightSample, LightSource};
+use std::f64;
+
+// A light infinitely far away like the sun, all of its rays are parallel. Its intensity is
+// the irradiance on a surface facing it, which does not fall off with the distance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectionalLight {
+    pub intensity: f64,
+    // The normalized direction the light travels in
+    pub direction: Vector3,
+    // The color of the light in [0, 255]
+    pub color: Vector3,
+}
+
+impl DirectionalLight {
+    pub fn new(intensity: f64, direction: Vector3) -> DirectionalLight {
+        DirectionalLight {
+            intensity,
+            direction: direction.normalize(),
+            color: Vector3::new(255.0, 255.0, 255.0),
+        }
+    }
+}
+
+impl LightSource for DirectionalLight {
+    fn illuminate(&self, _point: &Vector3, _rng: &mut Rng) -> Vec<LightSample> {
+        // Every object in the opposite direction of the light casts a shadow
+        vec![LightSample {
+            direction: self.direction.inverse(),
+            distance: f64::INFINITY,
+            intensity: tinted(&self.color, self.intensity),
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_light_everywhere() {
+        let light = DirectionalLight::new(1.5, Vector3::new(0.0, -2.0, 0.0));
+        let near = light.illuminate(&Vector3::zero(), &mut Rng::new(0));
+        let far = light.illuminate(&Vector3::new(1e6, -1e6, 3.0), &mut Rng::new(0));
+        assert_eq!(near, far);
+        let near = &near[0];
+        assert_eq!(near.direction, Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(near.distance, f64::INFINITY);
+        assert_eq!(near.intensity, Vector3::new(1.5, 1.5, 1.5));
+    }
+}
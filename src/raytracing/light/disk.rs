@@ -0,0 +1,121 @@
+use math::{stratified_samples, Rng, Vector3};
+use raytracing::light::light_source::surface_samples;
+use raytracing::{Falloff, LightSample, LightSource, LightUnit, Ray};
+use shapes::intersect_plane;
+use std::f64::consts::PI;
+
+// A round disk around center, which emits light from the side its normal points to. Its
+// intensity is the radiant intensity along the normal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiskLight {
+    pub intensity: f64,
+    pub center: Vector3,
+    pub normal: Vector3,
+    pub radius: f64,
+    // The color of the light in [0, 255]
+    pub color: Vector3,
+    pub falloff: Falloff,
+    pub unit: LightUnit,
+    // The number of shadow rays sent towards the light from every shading point
+    pub samples: u32,
+}
+
+impl DiskLight {
+    pub fn new(intensity: f64, center: Vector3, normal: Vector3, radius: f64) -> DiskLight {
+        DiskLight {
+            intensity,
+            center,
+            normal: normal.normalize(),
+            radius,
+            color: Vector3::new(255.0, 255.0, 255.0),
+            falloff: Falloff::None,
+            unit: LightUnit::WattsPerSteradian,
+            samples: 16,
+        }
+    }
+
+    pub fn area(&self) -> f64 {
+        PI * self.radius * self.radius
+    }
+
+    pub fn radiant_intensity(&self) -> f64 {
+        // Like for rectangles, the power divided by pi is sent along the normal
+        self.unit.radiant_intensity(self.intensity, PI)
+    }
+}
+
+impl LightSource for DiskLight {
+    fn illuminate(&self, point: &Vector3, rng: &mut Rng) -> Vec<LightSample> {
+        // The square root keeps the samples evenly distributed over the area
+        let (tangent, bitangent) = self.normal.orthonormal_basis();
+        let positions = stratified_samples(self.samples.max(1), rng)
+            .into_iter()
+            .map(|(s, t)| {
+                let radius = self.radius * s.sqrt();
+                let angle = 2.0 * PI * t;
+                &(&self.center + &(&tangent * (radius * angle.cos())))
+                    + &(&bitangent * (radius * angle.sin()))
+            })
+            .collect();
+        surface_samples(
+            point,
+            positions,
+            self.radiant_intensity(),
+            &self.color,
+            self.falloff,
+            |_, direction| direction % &self.normal,
+        )
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<(f64, Vector3)> {
+        let t = intersect_plane(ray, &self.center, &self.normal)?;
+        if (&ray.get_coordinates(t) - &self.center).len() > self.radius {
+            return None;
+        }
+        // Seen from behind, the light is black
+        if &ray.direction % &self.normal >= 0.0 {
+            return Some((t, Vector3::zero()));
+        }
+        Some((t, &self.color * (self.radiant_intensity() / self.area())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples_lie_on_the_disk() {
+        let light = DiskLight::new(
+            2.0,
+            Vector3::new(0.0, 0.0, 5.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            1.5,
+        );
+        let samples = light.illuminate(&Vector3::zero(), &mut Rng::new(3));
+        assert_eq!(samples.len(), 16);
+        for sample in &samples {
+            let position = &sample.direction * sample.distance;
+            assert!((position.z - 5.0).abs() < 1e-12);
+            assert!((&position - &light.center).len() <= 1.5);
+        }
+        let total: f64 = samples.iter().map(|sample| sample.intensity.x).sum();
+        assert!(total < 2.0 && total > 1.8);
+    }
+
+    #[test]
+    fn test_intersect() {
+        let light = DiskLight::new(
+            PI,
+            Vector3::new(0.0, 0.0, 5.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            1.0,
+        );
+        let ray = Ray::new(Vector3::zero(), Vector3::new(0.0, 0.19, 1.0));
+        let (t, color) = light.intersect(&ray).unwrap();
+        assert_eq!(t, 5.0);
+        assert_eq!(color, Vector3::new(255.0, 255.0, 255.0));
+        let ray = Ray::new(Vector3::zero(), Vector3::new(0.0, 0.21, 1.0));
+        assert!(light.intersect(&ray).is_none());
+    }
+}
@@ -0,0 +1,189 @@
+use math::{stratified_samples, Rng, Vector3};
+use raytracing::light::light_source::surface_samples;
+use raytracing::{Falloff, LightSample, LightSource, SphereLight};
+use std::f64::consts::PI;
+
+// The light given off by triangles with an emissive material, like the faces of a mesh. They
+// all emit the same radiance from their front side and every triangle receives a share of the
+// samples proportional to its area.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmissiveMesh {
+    triangles: Vec<(Vector3, Vector3, Vector3)>,
+    // The area of all triangles up to and including the one at the same index
+    cumulative_areas: Vec<f64>,
+    // The emission of the material
+    pub emission: Vector3,
+    // The number of shadow rays sent towards the mesh from every shading point
+    pub samples: u32,
+}
+
+impl EmissiveMesh {
+    pub fn new(emission: Vector3) -> EmissiveMesh {
+        EmissiveMesh {
+            triangles: Vec::new(),
+            cumulative_areas: Vec::new(),
+            emission,
+            samples: 16,
+        }
+    }
+
+    pub fn add_triangle(&mut self, v0: Vector3, v1: Vector3, v2: Vector3) {
+        let area = (&v1 - &v0).cross(&(&v2 - &v0)).len() / 2.0;
+        let total = self.area() + area;
+        self.cumulative_areas.push(total);
+        self.triangles.push((v0, v1, v2));
+    }
+
+    pub fn area(&self) -> f64 {
+        self.cumulative_areas.last().cloned().unwrap_or(0.0)
+    }
+
+    fn pick_triangle(&self, s: f64) -> (usize, f64) {
+        // Returns the triangle the fraction s of the total area falls into and where within
+        // the triangle's own share of the area it lies, which is again uniform in [0, 1)
+        let target = s * self.area();
+        let index = self
+            .cumulative_areas
+            .partition_point(|&sum| sum <= target)
+            .min(self.triangles.len() - 1);
+        let start = if index == 0 {
+            0.0
+        } else {
+            self.cumulative_areas[index - 1]
+        };
+        let end = self.cumulative_areas[index];
+        (index, ((target - start) / (end - start)).clamp(0.0, 1.0))
+    }
+}
+
+impl LightSource for EmissiveMesh {
+    fn illuminate(&self, point: &Vector3, rng: &mut Rng) -> Vec<LightSample> {
+        if self.area() <= 0.0 {
+            return Vec::new();
+        }
+        let count = self.samples.max(1);
+        // Each sample stands for an equal part of the area, so sampling by area keeps all of
+        // them equally bright
+        let share = self.area() / count as f64;
+        let mut samples = Vec::new();
+        for (s, t) in stratified_samples(count, rng) {
+            let (index, s) = self.pick_triangle(s);
+            let (ref v0, ref v1, ref v2) = self.triangles[index];
+            // The square root keeps the positions evenly distributed over the triangle
+            let root = s.sqrt();
+            let position =
+                &(&(v0 * (1.0 - root)) + &(v1 * (root * (1.0 - t)))) + &(v2 * (root * t));
+            let normal = (v1 - v0).cross(&(v2 - v0)).normalize();
+            samples.extend(surface_samples(
+                point,
+                vec![position],
+                share,
+                &self.emission,
+                Falloff::InverseSquare,
+                |_, direction| direction % &normal,
+            ));
+        }
+        samples
+    }
+}
+
+// The light given off by a sphere with an emissive material. The sphere itself is traced as a
+// shape of the scene, so unlike a sphere light this can't be hit by rays.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmissiveSphere {
+    pub light: SphereLight,
+}
+
+impl EmissiveSphere {
+    pub fn new(center: Vector3, radius: f64, emission: Vector3) -> EmissiveSphere {
+        // A sphere light shows its intensity divided by its cross section, so this intensity
+        // makes it show exactly the emission
+        EmissiveSphere {
+            light: SphereLight {
+                color: emission,
+                falloff: Falloff::InverseSquare,
+                ..SphereLight::new(PI * radius * radius, center, radius)
+            },
+        }
+    }
+}
+
+impl LightSource for EmissiveSphere {
+    fn illuminate(&self, point: &Vector3, rng: &mut Rng) -> Vec<LightSample> {
+        self.light.illuminate(point, rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use raytracing::Ray;
+
+    #[test]
+    fn test_triangles_are_sampled_by_area() {
+        // A large and a small triangle in the plane y = 2, both facing down
+        let mut mesh = EmissiveMesh::new(Vector3::new(255.0, 255.0, 255.0));
+        mesh.add_triangle(
+            Vector3::new(0.0, 2.0, 0.0),
+            Vector3::new(3.0, 2.0, 0.0),
+            Vector3::new(0.0, 2.0, 3.0),
+        );
+        mesh.add_triangle(
+            Vector3::new(-1.0, 2.0, 0.0),
+            Vector3::new(-1.0, 2.0, 1.0),
+            Vector3::new(-2.0, 2.0, 0.0),
+        );
+        assert_eq!(mesh.area(), 5.0);
+        mesh.samples = 100;
+        let samples = mesh.illuminate(&Vector3::zero(), &mut Rng::new(2));
+        assert_eq!(samples.len(), 100);
+        let on_small = samples
+            .iter()
+            .filter(|sample| sample.direction.x < 0.0)
+            .count();
+        assert_eq!(on_small, 10);
+        for sample in &samples {
+            let position = &sample.direction * sample.distance;
+            assert!((position.y - 2.0).abs() < 1e-12);
+            if position.x >= 0.0 {
+                assert!(position.x + position.z <= 3.0 + 1e-12);
+            }
+        }
+
+        // Points above the triangles don't see their front side
+        assert!(mesh
+            .illuminate(&Vector3::new(0.0, 3.0, 0.0), &mut Rng::new(2))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_far_away_intensity_is_radiance_times_area() {
+        let mut mesh = EmissiveMesh::new(Vector3::new(510.0, 255.0, 0.0));
+        mesh.add_triangle(
+            Vector3::new(0.0, 0.0, 10.0),
+            Vector3::new(2.0, 0.0, 10.0),
+            Vector3::new(0.0, 2.0, 10.0),
+        );
+        // Seen from the front at a distance of 1000, the area of 2 looks like a point light
+        let samples = mesh.illuminate(&Vector3::new(0.0, 0.0, 1010.0), &mut Rng::new(0));
+        let total = samples
+            .iter()
+            .fold(Vector3::zero(), |total, sample| &total + &sample.intensity);
+        assert!((total.x * 1000.0 * 1000.0 - 4.0).abs() < 1e-3);
+        assert!((total.y * 1000.0 * 1000.0 - 2.0).abs() < 1e-3);
+        assert_eq!(total.z, 0.0);
+    }
+
+    #[test]
+    fn test_sphere_uses_the_emission() {
+        let sphere = EmissiveSphere::new(Vector3::zero(), 2.0, Vector3::new(0.0, 255.0, 0.0));
+        let samples = sphere.illuminate(&Vector3::new(0.0, 0.0, 10.0), &mut Rng::new(0));
+        assert!(!samples.is_empty());
+        assert!(samples
+            .iter()
+            .all(|sample| sample.intensity.x == 0.0 && sample.intensity.y > 0.0));
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 10.0), Vector3::new(0.0, 0.0, -1.0));
+        assert!(sphere.intersect(&ray).is_none());
+        assert!(sphere.light.intersect(&ray).is_some());
+    }
+}
@@ -0,0 +1,235 @@
+use math::{stratified_samples, Rng, Vector3};
+use raytracing::{LightSample, LightSource};
+use std::f64::consts::PI;
+use util::framebuffer::{Framebuffer, LinearRGB};
+
+// An equirectangular image of everything around the scene. The center of the image lies
+// straight ahead along z, its top row straight up. Seen as a light it shines in from every
+// direction and is sampled in proportion to the brightness of the image, so that small bright
+// spots like the sun get most of the shadow rays.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvironmentMap {
+    image: Framebuffer,
+    // The turn of the map around the vertical axis in radians
+    pub rotation: f64,
+    // Scales the radiance of the image
+    pub intensity: f64,
+    // The number of shadow rays sent towards the map from every shading point
+    pub samples: u32,
+    // The cumulative distribution of the weight of the rows and of the pixels within every
+    // row, each normalized to end at 1
+    row_distribution: Vec<f64>,
+    pixel_distributions: Vec<Vec<f64>>,
+    // The average weight of all pixels, with which the weight of a pixel becomes its
+    // probability density over the image
+    average_weight: f64,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: Framebuffer, rotation: f64) -> EnvironmentMap {
+        // Pixels are weighted by their luminance and by the solid angle they cover, which
+        // shrinks towards the poles
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let mut row_weights = Vec::with_capacity(height);
+        let mut pixel_distributions = Vec::with_capacity(height);
+        for y in 0..height {
+            let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+            let row = &image.pixels()[y * width..(y + 1) * width];
+            let weights = row
+                .iter()
+                .map(|&pixel| luminance(pixel) * sin_theta)
+                .collect();
+            let (distribution, total) = cumulative_distribution(weights);
+            row_weights.push(total);
+            pixel_distributions.push(distribution);
+        }
+        let (row_distribution, total) = cumulative_distribution(row_weights);
+        EnvironmentMap {
+            average_weight: total / (width * height).max(1) as f64,
+            image,
+            rotation: rotation.to_radians(),
+            intensity: 1.0,
+            samples: 16,
+            row_distribution,
+            pixel_distributions,
+        }
+    }
+
+    // Returns the radiance arriving from the given direction, where 1.0 is white
+    pub fn radiance(&self, direction: &Vector3) -> Vector3 {
+        let (u, v) = self.image_coordinates(&direction.normalize());
+        let (x, y) = self.pixel(u, v);
+        self.pixel_radiance(x, y)
+    }
+
+    fn pixel(&self, u: f64, v: f64) -> (usize, usize) {
+        let width = self.image.width() as usize;
+        let height = self.image.height() as usize;
+        (
+            ((u * width as f64) as usize).min(width - 1),
+            ((v * height as f64) as usize).min(height - 1),
+        )
+    }
+
+    fn pixel_radiance(&self, x: usize, y: usize) -> Vector3 {
+        let pixel = self.image.pixels()[y * self.image.width() as usize + x];
+        &Vector3::new(f64::from(pixel.r), f64::from(pixel.g), f64::from(pixel.b)) * self.intensity
+    }
+
+    fn image_coordinates(&self, direction: &Vector3) -> (f64, f64) {
+        // Maps a normalized direction to coordinates in [0, 1] x [0, 1] with the top left
+        // corner of the image at 0, 0
+        let theta = direction.y.clamp(-1.0, 1.0).acos();
+        let phi = direction.x.atan2(direction.z) - self.rotation;
+        ((phi / (2.0 * PI) + 0.5).rem_euclid(1.0), theta / PI)
+    }
+
+    fn direction_at(&self, u: f64, v: f64) -> Vector3 {
+        let phi = (u - 0.5) * 2.0 * PI + self.rotation;
+        let theta = v * PI;
+        Vector3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            theta.sin() * phi.cos(),
+        )
+    }
+}
+
+impl LightSource for EnvironmentMap {
+    fn illuminate(&self, _point: &Vector3, rng: &mut Rng) -> Vec<LightSample> {
+        if self.average_weight <= 0.0 {
+            return Vec::new();
+        }
+        let count = self.samples.max(1);
+        let height = self.pixel_distributions.len();
+        let mut samples = Vec::with_capacity(count as usize);
+        for (s, t) in stratified_samples(count, rng) {
+            // Picks a row, then a pixel within it, and keeps where the random numbers fall
+            // within the pixel to spread the directions over its area
+            let (y, dv) = pick(&self.row_distribution, s);
+            let row = &self.pixel_distributions[y];
+            let (x, du) = pick(row, t);
+            let u = (x as f64 + du) / row.len() as f64;
+            let v = (y as f64 + dv) / height as f64;
+            let sin_theta = (v * PI).sin();
+            if sin_theta <= 0.0 {
+                continue;
+            }
+            // The density of the chosen direction per solid angle. An image of the whole
+            // sphere stretches every pixel over 2 pi^2 sin(theta) times its area.
+            let pixel = self.image.pixels()[y * row.len() + x];
+            let weight = luminance(pixel) * (PI * (y as f64 + 0.5) / height as f64).sin();
+            let density = weight / self.average_weight / (2.0 * PI * PI * sin_theta);
+            samples.push(LightSample {
+                direction: self.direction_at(u, v),
+                distance: f64::INFINITY,
+                intensity: &self.pixel_radiance(x, y) * (1.0 / (density * count as f64)),
+            });
+        }
+        samples
+    }
+}
+
+fn luminance(color: LinearRGB) -> f64 {
+    f64::from(0.2126 * color.r + 0.7152 * color.g + 0.0722 * color.b).max(0.0)
+}
+
+fn cumulative_distribution(weights: Vec<f64>) -> (Vec<f64>, f64) {
+    // Returns the running sums of the weights divided by their total, and the total
+    let total: f64 = weights.iter().sum();
+    let mut sum = 0.0;
+    let distribution = weights
+        .into_iter()
+        .map(|weight| {
+            sum += weight;
+            if total > 0.0 {
+                sum / total
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    (distribution, total)
+}
+
+fn pick(distribution: &[f64], s: f64) -> (usize, f64) {
+    // Returns the index s falls into and where in its part of the distribution it lies
+    let index = distribution
+        .partition_point(|&sum| sum <= s)
+        .min(distribution.len() - 1);
+    let start = if index == 0 {
+        0.0
+    } else {
+        distribution[index - 1]
+    };
+    let end = distribution[index];
+    if end > start {
+        (index, ((s - start) / (end - start)).clamp(0.0, 1.0))
+    } else {
+        (index, 0.5)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sky_with_sun() -> EnvironmentMap {
+        // A dim blue sky with a single bright pixel above the horizon, straight ahead
+        let mut image = Framebuffer::filled(64, 32, LinearRGB::new(0.0, 0.0, 0.1));
+        image.set_pixel(32, 12, LinearRGB::new(500.0, 500.0, 500.0));
+        EnvironmentMap::new(image, 0.0)
+    }
+
+    #[test]
+    fn test_directions_map_to_the_image() {
+        let map = sky_with_sun();
+        assert_eq!(map.radiance(&Vector3::new(0.0, 1.0, 0.0)).z, 0.1f32 as f64);
+        let sun = map.direction_at(32.5 / 64.0, 12.5 / 32.0);
+        assert!(sun.z > 0.0 && sun.y > 0.0 && sun.x.abs() < 0.1);
+        assert_eq!(map.radiance(&sun), Vector3::new(500.0, 500.0, 500.0));
+
+        // Turning the map by 90 degrees moves the sun to the right, which is +x
+        let map = EnvironmentMap::new(map.image.clone(), 90.0);
+        let sun = map.direction_at(32.5 / 64.0, 12.5 / 32.0);
+        assert!(sun.x > 0.0 && sun.z.abs() < 0.1);
+        assert_eq!(map.radiance(&sun), Vector3::new(500.0, 500.0, 500.0));
+        let (u, v) = map.image_coordinates(&sun.normalize());
+        assert!((u - 32.5 / 64.0).abs() < 1e-12 && (v - 12.5 / 32.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_samples_favor_bright_pixels() {
+        let map = EnvironmentMap {
+            samples: 64,
+            ..sky_with_sun()
+        };
+        let samples = map.illuminate(&Vector3::zero(), &mut Rng::new(4));
+        let towards_sun = samples
+            .iter()
+            .filter(|sample| sample.intensity.x > 0.0)
+            .count();
+        assert!(towards_sun > 56);
+        assert!(samples
+            .iter()
+            .all(|sample| sample.distance == f64::INFINITY));
+    }
+
+    #[test]
+    fn test_uniform_map_lights_like_its_radiance_times_pi() {
+        // A white environment sends a radiance of 1 from everywhere, the cosine weighted
+        // integral over the upper hemisphere is pi
+        let image = Framebuffer::filled(32, 16, LinearRGB::new(1.0, 1.0, 1.0));
+        let map = EnvironmentMap {
+            samples: 4096,
+            ..EnvironmentMap::new(image, 0.0)
+        };
+        let normal = Vector3::new(0.0, 1.0, 0.0);
+        let irradiance: f64 = map
+            .illuminate(&Vector3::zero(), &mut Rng::new(0))
+            .iter()
+            .map(|sample| sample.intensity.x * (&sample.direction % &normal).max(0.0))
+            .sum();
+        assert!((irradiance - PI).abs() < 0.05);
+    }
+}
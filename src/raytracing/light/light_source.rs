@@ -0,0 +1,111 @@
+use math::{Rng, Vector3};
+use raytracing::Ray;
+use std::f64::consts::PI;
+
+// The light arriving at a shading point from a light source
+#[derive(Debug, Clone, PartialEq)]
+pub struct LightSample {
+    // The normalized direction from the shading point towards the light
+    pub direction: Vector3,
+    // The distance to the light along direction, which is infinite for lights like the sun.
+    // Only objects closer than this cast shadows.
+    pub distance: f64,
+    // The light arriving at the point for every color channel
+    pub intensity: Vector3,
+}
+
+// Lights are shared between the render threads and therefore have to be Send and Sync
+pub trait LightSource: Send + Sync {
+    // Returns the light arriving at point, ignoring occlusion. Lights with a surface return
+    // samples spread over it, which add up to all of their light. Points that are not lit by
+    // this light at all get no samples.
+    fn illuminate(&self, point: &Vector3, rng: &mut Rng) -> Vec<LightSample>;
+
+    // Returns the ray parameter of the closest hit with the surface of the light and the color
+    // seen there in [0, 255]. Lights without a surface can not be seen.
+    fn intersect(&self, _ray: &Ray) -> Option<(f64, Vector3)> {
+        None
+    }
+}
+
+// How the light arriving at a point decreases with its distance from the light
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Falloff {
+    // Every point receives the full intensity, however far away it is
+    None,
+    Linear,
+    // The physically correct falloff of a point light
+    InverseSquare,
+}
+
+impl Falloff {
+    pub fn attenuation(self, distance: f64) -> f64 {
+        match self {
+            Falloff::None => 1.0,
+            Falloff::Linear => 1.0 / distance,
+            Falloff::InverseSquare => 1.0 / (distance * distance),
+        }
+    }
+}
+
+// The radiometric unit the intensity of a light is given in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightUnit {
+    // Radiant intensity, the power emitted per solid angle
+    WattsPerSteradian,
+    // Radiant power, spread evenly over all directions the light shines in
+    Watts,
+}
+
+impl LightUnit {
+    // Converts a value in this unit to watts per steradian for a light that emits into the
+    // given solid angle
+    pub fn radiant_intensity(self, value: f64, solid_angle: f64) -> f64 {
+        match self {
+            LightUnit::WattsPerSteradian => value,
+            LightUnit::Watts => value / solid_angle,
+        }
+    }
+}
+
+// The solid angle of all directions around a point
+pub const FULL_SPHERE: f64 = 4.0 * PI;
+
+// Scales a color in [0, 255] to the light of the given strength
+pub fn tinted(color: &Vector3, intensity: f64) -> Vector3 {
+    &(color / 255.0) * intensity
+}
+
+// Turns points on the surface of an area light into light samples, each with an equal share of
+// the light's radiant intensity. The emission function scales the share of a position for the
+// normalized direction from it towards point, for flat lights this is the cosine to the normal.
+pub fn surface_samples<F>(
+    point: &Vector3,
+    positions: Vec<Vector3>,
+    radiant_intensity: f64,
+    color: &Vector3,
+    falloff: Falloff,
+    emission: F,
+) -> Vec<LightSample>
+where
+    F: Fn(&Vector3, &Vector3) -> f64,
+{
+    let share = radiant_intensity / positions.len() as f64;
+    positions
+        .into_iter()
+        .filter_map(|position| {
+            let to_light = &position - point;
+            let distance = to_light.len();
+            let direction = to_light.normalize();
+            let weight = emission(&position, &direction.inverse());
+            if weight <= 0.0 || distance == 0.0 {
+                return None;
+            }
+            Some(LightSample {
+                direction,
+                distance,
+                intensity: tinted(color, share * weight * falloff.attenuation(distance)),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,19 @@
+mod directional;
+mod disk;
+mod emissive;
+mod environment;
+mod light_source;
+mod point;
+mod rect;
+mod sphere;
+mod spot;
+
+pub use self::directional::DirectionalLight;
+pub use self::disk::DiskLight;
+pub use self::emissive::{EmissiveMesh, EmissiveSphere};
+pub use self::environment::EnvironmentMap;
+pub use self::light_source::{Falloff, LightSample, LightSource, LightUnit};
+pub use self::point::Light;
+pub use self::rect::RectLight;
+pub use self::sphere::SphereLight;
+pub use self::spot::SpotLight;
@@ -0,0 +1,96 @@
+use math::{Rng, Vector3};
+use raytracing::light::light_source::{tinted, FULL_SPHERE};
+use raytracing::{Falloff, LightSample, LightSource, LightUnit};
+
+// An omnidirectional point light
+#[derive(Debug, Clone, PartialEq)]
+pub struct Light {
+    pub intensity: f64,
+    pub origin: Vector3,
+    // The color of the light in [0, 255]
+    pub color: Vector3,
+    pub falloff: Falloff,
+    pub unit: LightUnit,
+}
+
+impl Light {
+    pub fn new(intensity: f64, origin: Vector3) -> Light {
+        // A white light that is equally bright at any distance
+        Light {
+            intensity,
+            origin,
+            color: Vector3::new(255.0, 255.0, 255.0),
+            falloff: Falloff::None,
+            unit: LightUnit::WattsPerSteradian,
+        }
+    }
+
+    pub fn radiant_intensity(&self) -> f64 {
+        self.unit.radiant_intensity(self.intensity, FULL_SPHERE)
+    }
+
+    // Returns the light arriving at the given distance for every color channel, a white
+    // light without falloff gives its intensity in every channel
+    pub fn intensity_at(&self, distance: f64) -> Vector3 {
+        tinted(
+            &self.color,
+            self.radiant_intensity() * self.falloff.attenuation(distance),
+        )
+    }
+}
+
+impl LightSource for Light {
+    fn illuminate(&self, point: &Vector3, _rng: &mut Rng) -> Vec<LightSample> {
+        let to_light = &self.origin - point;
+        let distance = to_light.len();
+        vec![LightSample {
+            direction: to_light.normalize(),
+            distance,
+            intensity: self.intensity_at(distance),
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn test_falloff() {
+        let mut light = Light::new(8.0, Vector3::zero());
+        assert_eq!(light.intensity_at(4.0), Vector3::new(8.0, 8.0, 8.0));
+        light.falloff = Falloff::Linear;
+        assert_eq!(light.intensity_at(4.0), Vector3::new(2.0, 2.0, 2.0));
+        light.falloff = Falloff::InverseSquare;
+        assert_eq!(light.intensity_at(4.0), Vector3::new(0.5, 0.5, 0.5));
+        // Twice the distance gives a quarter of the light
+        let ratio = light.intensity_at(10.0).x / light.intensity_at(20.0).x;
+        assert!((ratio - 4.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_color_and_power() {
+        let light = Light {
+            color: Vector3::new(255.0, 51.0, 0.0),
+            unit: LightUnit::Watts,
+            ..Light::new(4.0 * PI, Vector3::zero())
+        };
+        assert!((light.radiant_intensity() - 1.0).abs() < 1e-12);
+        let intensity = light.intensity_at(1.0);
+        assert!((intensity.x - 1.0).abs() < 1e-12);
+        assert!((intensity.y - 0.2).abs() < 1e-12);
+        assert_eq!(intensity.z, 0.0);
+    }
+
+    #[test]
+    fn test_illuminate() {
+        let light = Light::new(2.0, Vector3::new(0.0, 3.0, 4.0));
+        let samples = light.illuminate(&Vector3::zero(), &mut Rng::new(0));
+        assert_eq!(samples.len(), 1);
+        let sample = &samples[0];
+        assert_eq!(sample.direction, Vector3::new(0.0, 0.6, 0.8));
+        assert_eq!(sample.distance, 5.0);
+        assert_eq!(sample.intensity, Vector3::new(2.0, 2.0, 2.0));
+    }
+}
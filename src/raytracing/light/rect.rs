@@ -0,0 +1,146 @@
+use math::{stratified_samples, Rng, Vector3};
+use raytracing::light::light_source::surface_samples;
+use raytracing::{Falloff, LightSample, LightSource, LightUnit, Ray};
+use shapes::intersect_plane;
+use std::f64::consts::PI;
+
+// A rectangle spanned by the edge vectors u and v around its center, which emits light from
+// the side its normal u x v points to. Its intensity is the radiant intensity along the normal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RectLight {
+    pub intensity: f64,
+    pub center: Vector3,
+    pub u: Vector3,
+    pub v: Vector3,
+    // The color of the light in [0, 255]
+    pub color: Vector3,
+    pub falloff: Falloff,
+    pub unit: LightUnit,
+    // The number of shadow rays sent towards the light from every shading point
+    pub samples: u32,
+}
+
+impl RectLight {
+    pub fn new(intensity: f64, center: Vector3, u: Vector3, v: Vector3) -> RectLight {
+        RectLight {
+            intensity,
+            center,
+            u,
+            v,
+            color: Vector3::new(255.0, 255.0, 255.0),
+            falloff: Falloff::None,
+            unit: LightUnit::WattsPerSteradian,
+            samples: 16,
+        }
+    }
+
+    pub fn normal(&self) -> Vector3 {
+        self.u.cross(&self.v).normalize()
+    }
+
+    pub fn area(&self) -> f64 {
+        self.u.cross(&self.v).len()
+    }
+
+    pub fn radiant_intensity(&self) -> f64 {
+        // A flat surface that emits equal radiance in all directions of one side sends the
+        // power divided by pi along its normal
+        self.unit.radiant_intensity(self.intensity, PI)
+    }
+}
+
+impl LightSource for RectLight {
+    fn illuminate(&self, point: &Vector3, rng: &mut Rng) -> Vec<LightSample> {
+        let positions = stratified_samples(self.samples.max(1), rng)
+            .into_iter()
+            .map(|(s, t)| &(&self.center + &(&self.u * (s - 0.5))) + &(&self.v * (t - 0.5)))
+            .collect();
+        let normal = self.normal();
+        surface_samples(
+            point,
+            positions,
+            self.radiant_intensity(),
+            &self.color,
+            self.falloff,
+            |_, direction| direction % &normal,
+        )
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<(f64, Vector3)> {
+        let normal = self.normal();
+        let t = intersect_plane(ray, &self.center, &normal)?;
+        // Express the hit point relative to the center in multiples of u and v
+        let local = &ray.get_coordinates(t) - &self.center;
+        let n = self.u.cross(&self.v);
+        let a = &local.cross(&self.v) % &n / (&n % &n);
+        let b = &self.u.cross(&local) % &n / (&n % &n);
+        if a.abs() > 0.5 || b.abs() > 0.5 {
+            return None;
+        }
+        // Seen from behind, the light is black
+        if &ray.direction % &normal >= 0.0 {
+            return Some((t, Vector3::zero()));
+        }
+        Some((t, &self.color * (self.radiant_intensity() / self.area())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ceiling_light() -> RectLight {
+        // A 2 x 1 light at y = 2, shining down
+        RectLight::new(
+            4.0,
+            Vector3::new(0.0, 2.0, 0.0),
+            Vector3::new(2.0, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+        )
+    }
+
+    #[test]
+    fn test_samples_cover_the_rectangle() {
+        let light = ceiling_light();
+        assert_eq!(light.normal(), Vector3::new(0.0, -1.0, 0.0));
+        let samples = light.illuminate(&Vector3::zero(), &mut Rng::new(1));
+        assert_eq!(samples.len(), 16);
+        let mut total = 0.0;
+        for sample in &samples {
+            let position = &sample.direction * sample.distance;
+            assert!((position.y - 2.0).abs() < 1e-12);
+            assert!(position.x.abs() <= 1.0 && position.z.abs() <= 0.5);
+            total += sample.intensity.x;
+        }
+        // Slightly less than the intensity along the normal, as most samples are seen at an angle
+        assert!(total < 4.0 && total > 3.0);
+
+        // Points behind the light are not lit
+        assert!(light
+            .illuminate(&Vector3::new(0.0, 3.0, 0.0), &mut Rng::new(1))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_far_away_it_looks_like_a_point_light() {
+        let light = ceiling_light();
+        let samples = light.illuminate(&Vector3::new(0.0, -1000.0, 0.0), &mut Rng::new(1));
+        let total: f64 = samples.iter().map(|sample| sample.intensity.x).sum();
+        assert!((total - 4.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_visible_from_the_front() {
+        let light = ceiling_light();
+        let ray = Ray::new(Vector3::new(0.9, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        let (t, color) = light.intersect(&ray).unwrap();
+        assert_eq!(t, 2.0);
+        // The radiance is the intensity divided by the area
+        assert_eq!(color, Vector3::new(510.0, 510.0, 510.0));
+
+        let ray = Ray::new(Vector3::new(1.1, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        assert!(light.intersect(&ray).is_none());
+        let ray = Ray::new(Vector3::new(0.0, 3.0, 0.0), Vector3::new(0.0, -1.0, 0.0));
+        assert_eq!(light.intersect(&ray), Some((1.0, Vector3::zero())));
+    }
+}
@@ -0,0 +1,115 @@
+use math::{stratified_samples, Rng, Vector3};
+use raytracing::light::light_source::{surface_samples, FULL_SPHERE};
+use raytracing::{Falloff, LightSample, LightSource, LightUnit, Ray};
+use shapes::intersect_sphere;
+use std::f64::consts::PI;
+
+// A glowing sphere, which sends the same radiant intensity in every direction
+#[derive(Debug, Clone, PartialEq)]
+pub struct SphereLight {
+    pub intensity: f64,
+    pub center: Vector3,
+    pub radius: f64,
+    // The color of the light in [0, 255]
+    pub color: Vector3,
+    pub falloff: Falloff,
+    pub unit: LightUnit,
+    // The number of shadow rays sent towards the light from every shading point
+    pub samples: u32,
+}
+
+impl SphereLight {
+    pub fn new(intensity: f64, center: Vector3, radius: f64) -> SphereLight {
+        SphereLight {
+            intensity,
+            center,
+            radius,
+            color: Vector3::new(255.0, 255.0, 255.0),
+            falloff: Falloff::None,
+            unit: LightUnit::WattsPerSteradian,
+            samples: 16,
+        }
+    }
+
+    pub fn radiant_intensity(&self) -> f64 {
+        self.unit.radiant_intensity(self.intensity, FULL_SPHERE)
+    }
+}
+
+impl LightSource for SphereLight {
+    fn illuminate(&self, point: &Vector3, rng: &mut Rng) -> Vec<LightSample> {
+        // Only the half of the sphere facing the point is sampled. Uniformly distributed
+        // heights along the axis give evenly distributed positions on its surface.
+        let axis = (point - &self.center).normalize();
+        let (tangent, bitangent) = axis.orthonormal_basis();
+        let positions = stratified_samples(self.samples.max(1), rng)
+            .into_iter()
+            .map(|(s, t)| {
+                let height = s;
+                let ring = (1.0 - height * height).sqrt();
+                let angle = 2.0 * PI * t;
+                let offset = &(&(&tangent * (ring * angle.cos()))
+                    + &(&bitangent * (ring * angle.sin())))
+                    + &(&axis * height);
+                &self.center + &(&offset * self.radius)
+            })
+            .collect();
+        // Every patch of the surface emits along its normal, seen from afar the cosines of the
+        // half sphere average to 1/2
+        surface_samples(
+            point,
+            positions,
+            self.radiant_intensity(),
+            &self.color,
+            self.falloff,
+            |position, direction| 2.0 * (&(position - &self.center).normalize() % direction),
+        )
+    }
+
+    fn intersect(&self, ray: &Ray) -> Option<(f64, Vector3)> {
+        let t = intersect_sphere(ray, &self.center, self.radius)?;
+        let radiance = self.radiant_intensity() / (PI * self.radius * self.radius);
+        Some((t, &self.color * radiance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples_face_the_point() {
+        let light = SphereLight::new(3.0, Vector3::new(0.0, 10.0, 0.0), 0.5);
+        let samples = light.illuminate(&Vector3::zero(), &mut Rng::new(5));
+        // Some positions close to the rim can not be seen from the point
+        assert!(samples.len() > 12 && samples.len() <= 16);
+        for sample in &samples {
+            let position = &sample.direction * sample.distance;
+            assert!(((&position - &light.center).len() - 0.5).abs() < 1e-12);
+            assert!(position.y <= 10.0);
+        }
+    }
+
+    #[test]
+    fn test_far_away_it_looks_like_a_point_light() {
+        let light = SphereLight {
+            samples: 256,
+            ..SphereLight::new(3.0, Vector3::new(0.0, 1000.0, 0.0), 0.5)
+        };
+        let samples = light.illuminate(&Vector3::zero(), &mut Rng::new(5));
+        let total: f64 = samples.iter().map(|sample| sample.intensity.x).sum();
+        assert!((total - 3.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_intersect() {
+        let light = SphereLight {
+            unit: LightUnit::Watts,
+            ..SphereLight::new(4.0 * PI * PI, Vector3::new(0.0, 0.0, 5.0), 1.0)
+        };
+        let ray = Ray::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0));
+        let (t, color) = light.intersect(&ray).unwrap();
+        assert_eq!(t, 4.0);
+        assert!((color.x - 255.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,126 @@
+use math::{Rng, Vector3};
+use raytracing::light::light_source::tinted;
+use raytracing::{Falloff, LightSample, LightSource, LightUnit};
+use std::f64::consts::PI;
+
+// A point light that only shines into a cone. Points within the inner angle of the cone's
+// axis receive the full intensity, which smoothly fades out towards the outer angle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpotLight {
+    pub intensity: f64,
+    pub origin: Vector3,
+    // The normalized axis of the cone, pointing away from the light
+    pub direction: Vector3,
+    // The angles between the axis and the edges of the cone in radians
+    pub inner_angle: f64,
+    pub outer_angle: f64,
+    // The color of the light in [0, 255]
+    pub color: Vector3,
+    pub falloff: Falloff,
+    pub unit: LightUnit,
+}
+
+impl SpotLight {
+    // The angles are given in degrees
+    pub fn new(
+        intensity: f64,
+        origin: Vector3,
+        direction: Vector3,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> SpotLight {
+        SpotLight {
+            intensity,
+            origin,
+            direction: direction.normalize(),
+            inner_angle: inner_angle.to_radians(),
+            outer_angle: outer_angle.to_radians(),
+            color: Vector3::new(255.0, 255.0, 255.0),
+            falloff: Falloff::None,
+            unit: LightUnit::WattsPerSteradian,
+        }
+    }
+
+    pub fn radiant_intensity(&self) -> f64 {
+        // The power is spread over the outer cone
+        let solid_angle = 2.0 * PI * (1.0 - self.outer_angle.cos());
+        self.unit.radiant_intensity(self.intensity, solid_angle)
+    }
+
+    // Returns how much of the light leaves in the given normalized direction, between 0 and 1
+    fn cone_attenuation(&self, direction: &Vector3) -> f64 {
+        let cos_angle = direction % &self.direction;
+        let (cos_inner, cos_outer) = (self.inner_angle.cos(), self.outer_angle.cos());
+        if cos_angle >= cos_inner {
+            return 1.0;
+        }
+        if cos_angle <= cos_outer {
+            return 0.0;
+        }
+        let t = (cos_angle - cos_outer) / (cos_inner - cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl LightSource for SpotLight {
+    fn illuminate(&self, point: &Vector3, _rng: &mut Rng) -> Vec<LightSample> {
+        let to_light = &self.origin - point;
+        let distance = to_light.len();
+        let direction = to_light.normalize();
+        let cone = self.cone_attenuation(&direction.inverse());
+        if cone == 0.0 {
+            return Vec::new();
+        }
+        let intensity = self.radiant_intensity() * self.falloff.attenuation(distance) * cone;
+        vec![LightSample {
+            direction,
+            distance,
+            intensity: tinted(&self.color, intensity),
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cone() {
+        // Shining straight down from y = 1 with a cone from 30 to 45 degrees
+        let light = SpotLight::new(
+            2.0,
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0),
+            30.0,
+            45.0,
+        );
+        let intensity = |x: f64| {
+            light
+                .illuminate(&Vector3::new(x, 0.0, 0.0), &mut Rng::new(0))
+                .first()
+                .map(|sample| sample.intensity.x)
+        };
+        assert_eq!(intensity(0.0), Some(2.0));
+        assert_eq!(intensity(0.5), Some(2.0));
+        // Half way between the angles of the inner and outer edge
+        let middle = intensity(37.5f64.to_radians().tan()).unwrap();
+        assert!(middle > 0.5 && middle < 1.5);
+        assert!(intensity(0.9).unwrap() < middle);
+        assert_eq!(intensity(1.01), None);
+        assert_eq!(intensity(-1.01), None);
+        // Points behind the light are never lit
+        assert!(light
+            .illuminate(&Vector3::new(0.0, 2.0, 0.0), &mut Rng::new(0))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_power_is_spread_over_the_cone() {
+        let light = SpotLight {
+            unit: LightUnit::Watts,
+            ..SpotLight::new(PI, Vector3::zero(), Vector3::new(1.0, 0.0, 0.0), 60.0, 60.0)
+        };
+        // A cone of 60 degrees covers a quarter of the sphere
+        assert!((light.radiant_intensity() - 1.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,145 @@
+use math::Vector3;
+
+// Describes how the surface of a shape is shaded. Colors are given in [0, 255].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    // The diffuse color of the object
+    pub color: Vector3,
+    // The lambertian coefficient, scaling the diffuse reflection
+    pub diffuse: f64,
+    // The color of the specular highlight, which is added to the diffuse color scaled by the
+    // light. Like the hard-coded shading before, new materials use the object's color.
+    pub specular_color: Vector3,
+    // The strength of the specular highlight, zero gives a matte surface
+    pub specular: f64,
+    // Determines the size of the specular highlight, larger values give smaller highlights
+    pub shininess: i32,
+    // Scales the ambient light of the scene for this object
+    pub ambient: f64,
+    // How much of the color comes from mirror reflection, between 0 and 1
+    pub reflectivity: f64,
+    // How much of the color comes from light passing through the object, between 0 and 1.
+    // Transparent objects are dielectrics like glass or water, which reflect and refract
+    // light depending on the angle it hits them at.
+    pub transparency: f64,
+    pub index_of_refraction: f64,
+    // The fraction of light absorbed per unit of distance traveled inside the object for
+    // every color channel, which colors thick glass more strongly than thin glass
+    pub absorption: Vector3,
+    // The light the surface gives off by itself. It is seen even in the dark, and emissive
+    // spheres and triangles light the rest of the scene like area lights.
+    pub emission: Vector3,
+}
+
+impl Material {
+    pub fn new(color: Vector3) -> Material {
+        // A slightly glossy material with a highlight in the object's color
+        Material {
+            specular_color: color.clone(),
+            color,
+            diffuse: 1.7,
+            specular: 1.0,
+            shininess: 25,
+            ambient: 1.0,
+            reflectivity: 0.0,
+            transparency: 0.0,
+            index_of_refraction: 1.0,
+            absorption: Vector3::zero(),
+            emission: Vector3::zero(),
+        }
+    }
+
+    pub fn matte(color: Vector3) -> Material {
+        Material {
+            specular: 0.0,
+            ..Material::new(color)
+        }
+    }
+
+    pub fn glossy(color: Vector3, shininess: i32) -> Material {
+        Material {
+            specular_color: Vector3::new(255.0, 255.0, 255.0),
+            shininess,
+            ..Material::new(color)
+        }
+    }
+
+    pub fn mirror(color: Vector3, reflectivity: f64) -> Material {
+        Material {
+            reflectivity,
+            ..Material::glossy(color, 60)
+        }
+    }
+
+    pub fn emissive(emission: Vector3) -> Material {
+        // A lamp, which only shows the light it emits
+        Material {
+            emission,
+            ambient: 0.0,
+            ..Material::matte(Vector3::zero())
+        }
+    }
+
+    pub fn glass(index_of_refraction: f64, absorption: Vector3) -> Material {
+        Material {
+            transparency: 1.0,
+            index_of_refraction,
+            absorption,
+            ..Material::glossy(Vector3::zero(), 100)
+        }
+    }
+}
+
+// Returns the fraction of light reflected at the boundary between two dielectrics with
+// refractive indices n1 and n2, where the rest is transmitted. cos_incident is the cosine
+// of the angle between the incoming light and the surface normal.
+// From https://en.wikipedia.org/wiki/Fresnel_equations
+pub fn fresnel(cos_incident: f64, n1: f64, n2: f64) -> f64 {
+    let sin_transmitted = n1 / n2 * (1.0 - cos_incident * cos_incident).max(0.0).sqrt();
+    if sin_transmitted >= 1.0 {
+        // Total internal reflection
+        return 1.0;
+    }
+    let cos_transmitted = (1.0 - sin_transmitted * sin_transmitted).max(0.0).sqrt();
+    let s_polarized =
+        (n1 * cos_incident - n2 * cos_transmitted) / (n1 * cos_incident + n2 * cos_transmitted);
+    let p_polarized =
+        (n2 * cos_incident - n1 * cos_transmitted) / (n2 * cos_incident + n1 * cos_transmitted);
+    // Unpolarized light is an even mix of both polarizations
+    (s_polarized * s_polarized + p_polarized * p_polarized) / 2.0
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material::new(Vector3::new(255.0, 255.0, 255.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fresnel_at_normal_incidence() {
+        // ((n1 - n2) / (n1 + n2))^2 for both directions
+        assert!((fresnel(1.0, 1.0, 1.5) - 0.04).abs() < 1e-15);
+        assert!((fresnel(1.0, 1.5, 1.0) - 0.04).abs() < 1e-15);
+    }
+
+    #[test]
+    fn test_fresnel_increases_towards_grazing_angles() {
+        let mut previous = fresnel(1.0, 1.0, 1.5);
+        for degrees in 1..90 {
+            let reflectance = fresnel(f64::from(degrees).to_radians().cos(), 1.0, 1.5);
+            assert!(reflectance >= previous);
+            previous = reflectance;
+        }
+        assert!((fresnel(0.0, 1.0, 1.5) - 1.0).abs() < 1e-15);
+    }
+
+    #[test]
+    fn test_fresnel_total_internal_reflection() {
+        let cos_incident = 60.0_f64.to_radians().cos();
+        assert_eq!(fresnel(cos_incident, 1.5, 1.0), 1.0);
+    }
+}
@@ -1,9 +1,22 @@
+mod background;
+mod bvh;
 mod camera;
+mod intersection;
+mod material;
 mod ray;
+mod renderer;
 mod scene;
 mod light;
 
-pub use self::camera::Camera;
+pub use self::background::Background;
+pub use self::bvh::Bvh;
+pub use self::camera::{Camera, FovAxis};
+pub use self::intersection::Intersection;
+pub use self::material::{fresnel, Material};
 pub use self::ray::Ray;
+pub use self::renderer::Renderer;
 pub use self::scene::Scene;
-pub use self::light::Light;
+pub use self::light::{
+    DirectionalLight, DiskLight, EmissiveMesh, EmissiveSphere, EnvironmentMap, Falloff, Light,
+    LightSample, LightSource, LightUnit, RectLight, SphereLight, SpotLight,
+};
@@ -0,0 +1,222 @@
+use raytracing::{Camera, Scene};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use util::framebuffer::{Framebuffer, LinearRGB};
+
+// Renders an image by splitting it into square tiles, which a pool of worker threads
+// takes from a shared queue until all of them are done
+pub struct Renderer {
+    pub threads: usize,
+    pub tile_size: u32,
+}
+
+struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Renderer {
+    pub fn new(threads: usize) -> Renderer {
+        // Passing zero threads uses one thread per available core
+        let threads = if threads == 0 {
+            thread::available_parallelism().map_or(1, |n| n.get())
+        } else {
+            threads
+        };
+        Renderer {
+            threads,
+            tile_size: 32,
+        }
+    }
+
+    fn tiles(&self, width: u32, height: u32) -> Vec<Tile> {
+        let mut tiles = Vec::new();
+        for y in (0..height).step_by(self.tile_size as usize) {
+            for x in (0..width).step_by(self.tile_size as usize) {
+                tiles.push(Tile {
+                    x,
+                    y,
+                    width: self.tile_size.min(width - x),
+                    height: self.tile_size.min(height - y),
+                });
+            }
+        }
+        tiles
+    }
+
+    pub fn render(&self, scene: &Scene, camera: &Camera, width: u32, height: u32) -> Framebuffer {
+        let tiles = self.tiles(width, height);
+        let next_tile = AtomicUsize::new(0);
+
+        let rendered_tiles: Vec<(usize, Vec<LinearRGB>)> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut rendered = Vec::new();
+                        loop {
+                            let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                            if index >= tiles.len() {
+                                break;
+                            }
+                            rendered.push((index, render_tile(scene, camera, &tiles[index])));
+                        }
+                        rendered
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("Render thread panicked"))
+                .collect()
+        });
+
+        let mut framebuffer = Framebuffer::new(width, height);
+        for (index, pixels) in rendered_tiles {
+            let tile = &tiles[index];
+            for (i, rgb) in pixels.into_iter().enumerate() {
+                let x = tile.x + i as u32 % tile.width;
+                let y = tile.y + i as u32 / tile.width;
+                framebuffer.set_pixel(x, y, rgb);
+            }
+        }
+        framebuffer
+    }
+}
+
+fn render_tile(scene: &Scene, camera: &Camera, tile: &Tile) -> Vec<LinearRGB> {
+    let mut pixels = Vec::with_capacity((tile.width * tile.height) as usize);
+    for y in tile.y..tile.y + tile.height {
+        for x in tile.x..tile.x + tile.width {
+            pixels.push(camera.get_pixel_color(scene, x, y));
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use math::Vector3;
+    use raytracing::{Light, Material};
+    use shapes::{Plane, Sphere};
+    use util::framebuffer::RGB;
+
+    fn test_scene() -> Scene {
+        let lights = vec![
+            Light::new(1.2, Vector3::new(0.0, -5.0, 4.0)),
+            Light::new(1.5, Vector3::new(5.0, 0.0, 4.0)),
+        ];
+        let mut scene = Scene::new(lights, 0.1);
+        scene.add_shape(Sphere::new(
+            Vector3::new(0.0, 0.0, 5.0),
+            1.5,
+            Material::new(Vector3::red()),
+        ));
+        scene.add_shape(Sphere::new(
+            Vector3::new(2.0, 2.0, 5.0),
+            1.0,
+            Material::new(Vector3::orange()),
+        ));
+        scene.add_shape(Plane::new(
+            Vector3::new(0.0, 3.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0),
+            Material::new(Vector3::green()),
+        ));
+        scene.build_bvh();
+        scene
+    }
+
+    #[test]
+    fn test_tiles_cover_image() {
+        let renderer = Renderer {
+            threads: 1,
+            tile_size: 16,
+        };
+        let tiles = renderer.tiles(40, 20);
+        assert_eq!(tiles.len(), 6);
+        let covered: u32 = tiles.iter().map(|tile| tile.width * tile.height).sum();
+        assert_eq!(covered, 40 * 20);
+    }
+
+    #[test]
+    fn test_multithreaded_output_is_identical() {
+        let scene = test_scene();
+        let camera = Camera::new(Vector3::new(0.0, 0.0, -5.0), 75.0, 60.0, 75.0);
+        let single = Renderer {
+            threads: 1,
+            tile_size: 64,
+        };
+        let multi = Renderer {
+            threads: 4,
+            tile_size: 7,
+        };
+        let expected = single.render(&scene, &camera, 75, 60);
+        let actual = multi.render(&scene, &camera, 75, 60);
+        assert!(expected == actual);
+    }
+
+    #[test]
+    fn test_up_is_at_the_top() {
+        // Only ambient light, so every sphere is rendered in its plain color
+        let mut scene = Scene::new(vec![], 1.0);
+        scene.add_shape(Sphere::new(
+            Vector3::new(0.0, 1.5, 5.0),
+            1.0,
+            Material::new(Vector3::new(255.0, 0.0, 0.0)),
+        ));
+        scene.add_shape(Sphere::new(
+            Vector3::new(0.0, -1.5, 5.0),
+            1.0,
+            Material::new(Vector3::new(0.0, 255.0, 0.0)),
+        ));
+        scene.add_shape(Sphere::new(
+            Vector3::new(2.5, 0.0, 5.0),
+            1.0,
+            Material::new(Vector3::new(0.0, 0.0, 255.0)),
+        ));
+        scene.build_bvh();
+        let renderer = Renderer::new(2);
+
+        for &(width, height) in &[(64, 36), (36, 64)] {
+            let camera = Camera::new_at_zero(width as f64, height as f64, 90.0);
+            let image = renderer.render(&scene, &camera, width, height);
+            assert_eq!((image.width(), image.height()), (width, height));
+
+            // Pixel that a point projects to, with a fov of 90 the plane at distance one
+            // spans [-1, 1] horizontally
+            let project = |x: f64, y: f64, z: f64| {
+                let half_height = height as f64 / width as f64;
+                (
+                    ((x / z + 1.0) / 2.0 * width as f64) as u32,
+                    ((1.0 - y / z / half_height) / 2.0 * height as f64) as u32,
+                )
+            };
+            let (x, y) = project(0.0, 1.5, 5.0);
+            assert!(y < height / 2);
+            assert_eq!(
+                image.get_pixel(x, y).map(LinearRGB::to_rgb),
+                Some(RGB::new(255, 0, 0))
+            );
+            let (x, y) = project(0.0, -1.5, 5.0);
+            assert!(y > height / 2);
+            assert_eq!(
+                image.get_pixel(x, y).map(LinearRGB::to_rgb),
+                Some(RGB::new(0, 255, 0))
+            );
+            let (x, y) = project(2.5, 0.0, 5.0);
+            assert!(x > width / 2);
+            assert_eq!(
+                image.get_pixel(x, y).map(LinearRGB::to_rgb),
+                Some(RGB::new(0, 0, 255))
+            );
+            let (x, y) = project(-2.5, 0.0, 5.0);
+            assert_eq!(
+                image.get_pixel(x, y).map(LinearRGB::to_rgb),
+                Some(RGB::new(127, 127, 127))
+            );
+        }
+    }
+}
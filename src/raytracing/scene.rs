@@ -1,101 +1,305 @@
-use math::Vector3;
+use math::{Rng, Vector3};
+use raytracing::fresnel;
+use raytracing::Background;
+use raytracing::Bvh;
+use raytracing::Intersection;
 use raytracing::Light;
+use raytracing::LightSource;
+use raytracing::Material;
 use raytracing::Ray;
-use shapes::Sphere;
+use raytracing::{EmissiveMesh, EmissiveSphere};
+use shapes::{MeshTriangle, Shape, Surface, TriangleMesh};
 use std::f64;
 
 pub struct Scene {
-    pub spheres: Vec<Sphere>,
-    pub lights: Vec<Light>,
+    pub shapes: Vec<Box<dyn Shape>>,
+    pub lights: Vec<Box<dyn LightSource>>,
+    // The lights of shapes with an emissive material, which are collected as they are added
+    pub emitters: Vec<Box<dyn LightSource>>,
     pub ambient_light: f64,
+    // What rays that don't hit anything see
+    pub background: Background,
+    // The maximum number of times a ray is reflected
+    pub max_depth: u32,
+    // Built by build_bvh and dropped whenever shapes are added
+    bvh: Option<Bvh>,
 }
 
 impl Scene {
     pub fn new(lights: Vec<Light>, ambient_light: f64) -> Scene {
         Scene {
-            spheres: Vec::new(),
-            lights,
+            shapes: Vec::new(),
+            lights: lights
+                .into_iter()
+                .map(|light| Box::new(light) as Box<dyn LightSource>)
+                .collect(),
+            emitters: Vec::new(),
             ambient_light,
+            background: Background::default(),
+            max_depth: 5,
+            bvh: None,
         }
     }
 
-    pub fn add_sphere(&mut self, sphere: Sphere) {
-        self.spheres.push(sphere);
+    pub fn add_shape<S: Shape + 'static>(&mut self, shape: S) {
+        let emission = &shape.material().emission;
+        if *emission != Vector3::zero() {
+            match shape.surface() {
+                Some(Surface::Sphere { center, radius }) => self.emitters.push(Box::new(
+                    EmissiveSphere::new(center, radius, emission.clone()),
+                )),
+                Some(Surface::Triangle(v0, v1, v2)) => {
+                    let mut emitter = EmissiveMesh::new(emission.clone());
+                    emitter.add_triangle(v0, v1, v2);
+                    self.emitters.push(Box::new(emitter));
+                }
+                None => (),
+            }
+        }
+        self.shapes.push(Box::new(shape));
+        self.bvh = None;
     }
 
-    pub fn trace_scene(&self, ray: &Ray) -> (Option<&Sphere>, f64) {
-        let mut t_result = f64::MAX;
-        let mut hit_sphere: Option<&Sphere> = None;
+    pub fn add_light<L: LightSource + 'static>(&mut self, light: L) {
+        self.lights.push(Box::new(light));
+    }
+
+    pub fn add_mesh(&mut self, mesh: TriangleMesh) {
+        // Every face becomes its own shape, all of them sharing the mesh's buffers. The faces
+        // of an emissive mesh become a single light, which picks faces by their area.
+        let mut emitter = EmissiveMesh::new(mesh.material.emission.clone());
+        let emissive = emitter.emission != Vector3::zero();
+        for triangle in MeshTriangle::from_mesh(mesh) {
+            if emissive {
+                if let Some(Surface::Triangle(v0, v1, v2)) = triangle.surface() {
+                    emitter.add_triangle(v0, v1, v2);
+                }
+            }
+            self.shapes.push(Box::new(triangle));
+        }
+        if emitter.area() > 0.0 {
+            self.emitters.push(Box::new(emitter));
+        }
+        self.bvh = None;
+    }
+
+    pub fn build_bvh(&mut self) {
+        // Should be called once all shapes were added and before rendering.
+        // Until then, the scene is traced by testing every shape.
+        self.bvh = Some(Bvh::build(&self.shapes));
+    }
+
+    pub fn trace_scene(&self, ray: &Ray) -> Option<Intersection> {
+        let closest = match self.bvh {
+            Some(ref bvh) => bvh.closest_hit(ray, &self.shapes),
+            None => self.closest_hit_linear(ray),
+        };
+
+        closest.map(|(id, t)| {
+            let shape = &self.shapes[id];
+            let point = ray.get_coordinates(t);
+            Intersection {
+                t,
+                normal: shape.get_normal(&point),
+                uv: shape.get_uv(&point),
+                point,
+                object_id: id,
+            }
+        })
+    }
+
+    // Returns whether any shape is hit by the ray with a ray parameter smaller than t_max
+    pub fn intersects_any(&self, ray: &Ray, t_max: f64) -> bool {
+        match self.bvh {
+            Some(ref bvh) => bvh.any_hit(ray, &self.shapes, t_max),
+            None => self
+                .shapes
+                .iter()
+                .any(|shape| shape.intersect(ray).is_some_and(|t| t < t_max)),
+        }
+    }
+
+    // Returns whether any object lies on the line segment from origin to target
+    pub fn is_occluded(&self, origin: &Vector3, target: &Vector3) -> bool {
+        let to_target = target - origin;
+        let distance = to_target.len();
+        self.is_occluded_along(origin, &to_target, distance)
+    }
+
+    // Returns whether any object lies within max_distance of origin in the given direction.
+    // This terminates on the first blocker found instead of looking for the closest one.
+    pub fn is_occluded_along(
+        &self,
+        origin: &Vector3,
+        direction: &Vector3,
+        max_distance: f64,
+    ) -> bool {
+        // With a normalized direction the ray parameter of a hit is its distance from origin
+        let shadow_ray = Ray::new(origin.clone(), direction.normalize());
+        // Objects right at the target, like the surface a light sits on, don't block it
+        self.intersects_any(&shadow_ray, max_distance * (1.0 - 1e-9))
+    }
 
-        for sphere in &self.spheres {
-            let res = sphere.intersect(ray);
+    fn closest_hit_linear(&self, ray: &Ray) -> Option<(usize, f64)> {
+        let mut t_result = f64::MAX;
+        let mut hit_id: Option<usize> = None;
 
-            match res {
-                Some(result) => {
-                    if result < t_result {
-                        t_result = result;
-                        hit_sphere = Some(sphere);
-                    }
+        for (id, shape) in self.shapes.iter().enumerate() {
+            if let Some(result) = shape.intersect(ray) {
+                if result < t_result {
+                    t_result = result;
+                    hit_id = Some(id);
                 }
-                None => (),
             }
         }
 
-        (hit_sphere, t_result)
+        hit_id.map(|id| (id, t_result))
+    }
+
+    // Returns the color seen along the ray, depth is the number of reflections so far
+    pub fn trace_color(&self, ray: &Ray, depth: u32, rng: &mut Rng) -> Vector3 {
+        let hit = self.trace_scene(ray);
+        let t_max = hit.as_ref().map_or(f64::INFINITY, |hit| hit.t);
+        if let Some(color) = self.closest_light_hit(ray, t_max) {
+            return color;
+        }
+        match hit {
+            Some(hit) => self.compute_color(ray, &hit, depth, rng),
+            None => self.background.color(&ray.direction),
+        }
+    }
+
+    // Returns the color of the closest light with a surface the ray hits before t_max
+    fn closest_light_hit(&self, ray: &Ray, t_max: f64) -> Option<Vector3> {
+        let mut closest = None;
+        let mut t_closest = t_max;
+        for light in self.lights.iter() {
+            if let Some((t, color)) = light.intersect(ray) {
+                if t < t_closest {
+                    t_closest = t;
+                    closest = Some(color);
+                }
+            }
+        }
+        closest
     }
 
     pub fn compute_color(
         &self,
         ray: &Ray,
-        intersection_point: Vector3,
-        hit_sphere: &Sphere,
+        hit: &Intersection,
+        depth: u32,
+        rng: &mut Rng,
     ) -> Vector3 {
-        // This is the lambertian coefficient for *this* object.
-        // This should be supplied by every object's material.
-        let lambertian_coefficient = 1.7;
-        // This determines the size of the specular highlight
-        let shininess_factor = 25;
+        let material = self.shapes[hit.object_id].material();
+        let intersection_point = &hit.point;
         // The base color is the color of the object scaled by the ambient light intensity
-        let mut color = &Vector3::zero() + &(&hit_sphere.color * self.ambient_light);
+        let mut color =
+            &Vector3::zero() + &(&material.color * (self.ambient_light * material.ambient));
 
-        for light in self.lights.iter() {
-            let point_to_light = &light.origin - &intersection_point;
-            // Get the distance t_light to the light
-            let t_light = point_to_light.len();
-
-            // We have to hit the light, therefore have to have a result,
-            // otherwise the calculation setup was wrong
-            // let t_light = light.intersect(&shadow_ray).unwrap();
-
-            let shadow_ray = Ray::new(intersection_point.clone(), point_to_light.clone());
-            let (hit_object, t_scene) = self.trace_scene(&shadow_ray);
+        let lights = self
+            .lights
+            .iter()
+            .chain(self.emitters.iter())
+            .map(|light| light.as_ref())
+            .chain(self.background.light());
+        for light in lights {
+            for sample in light.illuminate(intersection_point, rng) {
+                let point_to_light = sample.direction;
 
-            // Only if we didnt hit anything or if the light is closer than the object we hit
-            // -- meaning, there is no object between this point and the light -- do we calculate shading
-            if hit_object.is_none() || t_light < t_scene {
-                // We have illumination from the light source
-                let normal = hit_sphere.get_normal(&intersection_point);
+                // Only if there is no object between this point and the light do we calculate
+                // shading
+                if self.is_occluded_along(intersection_point, &point_to_light, sample.distance) {
+                    continue;
+                }
+                // We have illumination from the light source, tinted by its color and
+                // weakened by the distance it traveled. Every sample adds its own share, apart
+                // from the ambient light and the other lights.
+                let normal = hit.normal.clone();
+                let light_intensity = sample.intensity;
 
                 // Lambert Shading
                 let lambert_contribution =
-                    self.lambert_shading(&normal, &point_to_light, lambertian_coefficient)
-                        * light.intensity;
-                color = &color + &(&color * lambert_contribution);
+                    self.lambert_shading(&normal, &point_to_light, material.diffuse);
+                color = &color + &(&(&material.color * &light_intensity) * lambert_contribution);
 
                 // Specular Shading
                 let specular_contribution =
-                    self.specular_shading(&ray, normal, point_to_light, shininess_factor);
-                color = &color + &(&color * specular_contribution);
+                    self.specular_shading(ray, normal, point_to_light, material.shininess)
+                        * material.specular;
+                color = &color
+                    + &(&(&material.specular_color * &light_intensity) * specular_contribution);
             }
+        }
 
-            // Don't allow values larger than 255
-            color.x = color.x.min(255.0);
-            color.y = color.y.min(255.0);
-            color.z = color.z.min(255.0);
+        if material.reflectivity > 0.0 && depth < self.max_depth {
+            // Blend the local shading with what is seen in the mirror direction
+            let reflected_direction = ray.direction.normalize().reflect(&hit.normal);
+            let reflected_ray = Ray::new(intersection_point.clone(), reflected_direction);
+            let reflected_color = self.trace_color(&reflected_ray, depth + 1, rng);
+            color = &(&color * (1.0 - material.reflectivity))
+                + &(&reflected_color * material.reflectivity);
+        }
+
+        if material.transparency > 0.0 && depth < self.max_depth {
+            let transmitted_color = self.dielectric_color(ray, hit, material, depth, rng);
+            color = &(&color * (1.0 - material.transparency))
+                + &(&transmitted_color * material.transparency);
+        }
+
+        // A ray hitting the surface from the inside has traveled through the object, which
+        // absorbs light exponentially with the distance according to the Beer-Lambert law
+        if &ray.direction % &hit.normal > 0.0 && material.absorption != Vector3::zero() {
+            let distance = hit.t * ray.direction.len();
+            let absorption = &material.absorption;
+            color.x *= (-absorption.x * distance).exp();
+            color.y *= (-absorption.y * distance).exp();
+            color.z *= (-absorption.z * distance).exp();
+        }
+
+        // Emissive surfaces glow on the side their normal points to
+        if &ray.direction % &hit.normal < 0.0 {
+            color = &color + &material.emission;
         }
         color
     }
 
+    fn dielectric_color(
+        &self,
+        ray: &Ray,
+        hit: &Intersection,
+        material: &Material,
+        depth: u32,
+        rng: &mut Rng,
+    ) -> Vector3 {
+        // Splits the ray into a reflected and a refracted one, weighted by the Fresnel equations
+        let direction = ray.direction.normalize();
+        let mut normal = hit.normal.clone();
+        let mut cos_incident = -(&direction % &normal);
+        let (n1, n2) = if cos_incident < 0.0 {
+            // We are leaving the object, so the normal has to be flipped to point against the ray
+            normal = normal.inverse();
+            cos_incident = -cos_incident;
+            (material.index_of_refraction, 1.0)
+        } else {
+            (1.0, material.index_of_refraction)
+        };
+
+        let reflected_ray = Ray::new(hit.point.clone(), direction.reflect(&normal));
+        let reflected_color = self.trace_color(&reflected_ray, depth + 1, rng);
+
+        match direction.refract(&normal, n1 / n2) {
+            Some(refracted_direction) => {
+                let reflectance = fresnel(cos_incident, n1, n2);
+                let refracted_ray = Ray::new(hit.point.clone(), refracted_direction);
+                let refracted_color = self.trace_color(&refracted_ray, depth + 1, rng);
+                &(&reflected_color * reflectance) + &(&refracted_color * (1.0 - reflectance))
+            }
+            // Total internal reflection, all light is reflected
+            None => reflected_color,
+        }
+    }
+
     fn lambert_shading(
         &self,
         normal: &Vector3,
@@ -128,15 +332,19 @@ impl Scene {
         // We normalize both vectors because we are interested in the angle between them
         // This will result in values between -1 and 1.
         let dot_prod = &inverse_view_direction.normalize() % &(reflected_light_ray).normalize();
-        let specular_contribution = dot_prod.powi(shininess_factor);
-        // Only return values greater than 0
-        return specular_contribution.max(0.0);
+        // Only use values greater than 0, an even shininess would otherwise turn
+        // negative values into highlights
+        dot_prod.max(0.0).powi(shininess_factor)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use math::Rng;
+    use raytracing::{DirectionalLight, Falloff, Material, SpotLight};
+    use shapes::{Plane, Sphere, Triangle};
+    use std::f64::consts::PI;
 
     #[test]
     fn test_compute_color() {
@@ -144,17 +352,524 @@ mod tests {
         let lights = vec![Light::new(1.2, Vector3::new(0.0, 7.0, 3.0))];
         let mut scene = Scene::new(lights, 0.1);
 
-        let sphere = Sphere::new(Vector3::new(0.0, 0.0, 3.0), 1.0, Vector3::red());
+        let sphere = Sphere::new(
+            Vector3::new(0.0, 0.0, 3.0),
+            1.0,
+            Material::new(Vector3::red()),
+        );
         let ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0));
-        scene.add_sphere(sphere);
+        scene.add_shape(sphere);
+
+        let hit = Intersection {
+            t: 1.0,
+            point: Vector3::new(0.0, 1.0, 3.0),
+            normal: Vector3::new(0.0, 1.0, 0.0),
+            uv: (0.0, 1.0),
+            object_id: 0,
+        };
+        let color = scene.compute_color(&ray, &hit, 0, &mut Rng::new(0));
+
+        // Ambient term plus the lambert term with a coefficient of 1.7 and light intensity 1.2
+        let ambient_color = &Vector3::red() * scene.ambient_light;
+        assert_eq!(color, &ambient_color + &(&(&Vector3::red() * 1.2) * 1.7));
+    }
+
+    #[test]
+    fn test_compute_color_adds_lights_without_ambient_light() {
+        // A matte white sphere seen from the front, lit head on from the camera's side and
+        // from above
+        let front = Light::new(1.0, Vector3::new(0.0, 0.0, -5.0));
+        let above = Light::new(1.0, Vector3::new(0.0, 10.0, -5.0));
+        let mut scene = Scene::new(vec![front], 0.0);
+        scene.add_shape(Sphere::new(
+            Vector3::new(0.0, 0.0, 3.0),
+            1.0,
+            Material::matte(Vector3::new(255.0, 255.0, 255.0)),
+        ));
+        let ray = Ray::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0));
+        let hit = scene.trace_scene(&ray).unwrap();
+
+        let front_only = scene.compute_color(&ray, &hit, 0, &mut Rng::new(0));
+        assert_eq!(front_only, &Vector3::new(255.0, 255.0, 255.0) * 1.7);
+
+        // A second light adds its own share instead of scaling the first one
+        scene.lights = vec![Box::new(above)];
+        let above_only = scene.compute_color(&ray, &hit, 0, &mut Rng::new(0));
+        assert!(above_only.x > 0.0);
+        scene.add_light(Light::new(1.0, Vector3::new(0.0, 0.0, -5.0)));
+        let both = scene.compute_color(&ray, &hit, 0, &mut Rng::new(0));
+        assert!((&both - &(&front_only + &above_only)).len() < 1e-9);
+    }
+
+    #[test]
+    fn test_compute_color_uses_material() {
+        // Looking straight down onto the top of the sphere, with the light right above
+        let lights = vec![Light::new(1.2, Vector3::new(0.0, 7.0, 3.0))];
+        let mut scene = Scene::new(lights, 0.1);
+        let center = Vector3::new(0.0, 0.0, 3.0);
+        scene.add_shape(Sphere::new(
+            center.clone(),
+            1.0,
+            Material::matte(Vector3::red()),
+        ));
+        scene.add_shape(Sphere::new(
+            center,
+            1.0,
+            Material::glossy(Vector3::red(), 10),
+        ));
+        let ray = Ray::new(Vector3::new(0.0, 5.0, 3.0), Vector3::new(0.0, -1.0, 0.0));
+        let mut hit = Intersection {
+            t: 4.0,
+            point: Vector3::new(0.0, 1.0, 3.0),
+            normal: Vector3::new(0.0, 1.0, 0.0),
+            uv: (0.0, 1.0),
+            object_id: 0,
+        };
+
+        let matte = scene.compute_color(&ray, &hit, 0, &mut Rng::new(0));
+        let ambient_color = &Vector3::red() * scene.ambient_light;
+        assert_eq!(matte, &ambient_color + &(&(&Vector3::red() * 1.2) * 1.7));
+
+        // The highlight is white and scaled by the light, it is added on top without clamping
+        hit.object_id = 1;
+        let glossy = scene.compute_color(&ray, &hit, 0, &mut Rng::new(0));
+        assert_eq!(glossy, &matte + &(&Vector3::new(255.0, 255.0, 255.0) * 1.2));
+
+        // The default material has a highlight in the color of the object
+        scene.add_shape(Sphere::new(
+            Vector3::new(0.0, 0.0, 3.0),
+            1.0,
+            Material {
+                shininess: 10,
+                ..Material::new(Vector3::red())
+            },
+        ));
+        hit.object_id = 2;
+        let default = scene.compute_color(&ray, &hit, 0, &mut Rng::new(0));
+        assert_eq!(default, &matte + &(&Vector3::red() * 1.2));
+    }
+
+    #[test]
+    fn test_compute_color_with_colored_light_and_falloff() {
+        // A yellow light 6 units above the top of a matte white sphere
+        let light = Light {
+            color: Vector3::new(255.0, 255.0, 0.0),
+            falloff: Falloff::InverseSquare,
+            ..Light::new(36.0, Vector3::new(0.0, 7.0, 3.0))
+        };
+        let mut scene = Scene::new(vec![light.clone()], 0.1);
+        scene.add_shape(Sphere::new(
+            Vector3::new(0.0, 0.0, 3.0),
+            1.0,
+            Material::matte(Vector3::new(255.0, 255.0, 255.0)),
+        ));
+        let ray = Ray::new(Vector3::new(0.0, 5.0, 0.0), Vector3::new(0.0, -1.0, 1.0));
+        let hit = Intersection {
+            t: 3.0,
+            point: Vector3::new(0.0, 1.0, 3.0),
+            normal: Vector3::new(0.0, 1.0, 0.0),
+            uv: (0.0, 1.0),
+            object_id: 0,
+        };
+        let color = scene.compute_color(&ray, &hit, 0, &mut Rng::new(0));
+
+        // The light arrives with an intensity of 36 / 6^2 = 1 and has no blue in it
+        let ambient = 25.5;
+        assert!((color.x - (ambient + 255.0 * 1.7)).abs() < 1e-9);
+        assert_eq!(color.x, color.y);
+        assert_eq!(color.z, ambient);
+
+        // Moving the light twice as far away leaves a quarter of the diffuse light
+        scene.lights[0] = Box::new(Light {
+            origin: Vector3::new(0.0, 13.0, 3.0),
+            ..light
+        });
+        let color = scene.compute_color(&ray, &hit, 0, &mut Rng::new(0));
+        assert!((color.x - (ambient + 255.0 * 1.7 / 4.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_compute_color_per_light_type() {
+        // The top of a matte red sphere, with a small sphere far above it
+        let mut scene = Scene::new(Vec::new(), 0.1);
+        scene.add_shape(Sphere::new(
+            Vector3::new(0.0, 0.0, 3.0),
+            1.0,
+            Material::matte(Vector3::red()),
+        ));
+        scene.add_shape(Sphere::new(
+            Vector3::new(0.0, 100.0, 3.0),
+            0.5,
+            Material::matte(Vector3::green()),
+        ));
+        let ray = Ray::new(Vector3::new(0.0, 5.0, 0.0), Vector3::new(0.0, -1.0, 1.0));
+        let hit = Intersection {
+            t: 3.0,
+            point: Vector3::new(0.0, 1.0, 3.0),
+            normal: Vector3::new(0.0, 1.0, 0.0),
+            uv: (0.0, 1.0),
+            object_id: 0,
+        };
+        let ambient_color = &Vector3::red() * scene.ambient_light;
+        let lit_color = &ambient_color + &(&Vector3::red() * 1.7);
+
+        // Sunlight from straight above is blocked however far away the blocker is
+        scene.lights = vec![Box::new(DirectionalLight::new(
+            1.0,
+            Vector3::new(0.0, -1.0, 0.0),
+        ))];
+        assert_eq!(
+            scene.compute_color(&ray, &hit, 0, &mut Rng::new(0)),
+            ambient_color
+        );
+        // Coming in at an angle it passes the blocker and hits the surface at 60 degrees
+        scene.lights = vec![Box::new(DirectionalLight::new(
+            2.0,
+            Vector3::new(-(3.0f64.sqrt()), -1.0, 0.0),
+        ))];
+        let color = scene.compute_color(&ray, &hit, 0, &mut Rng::new(0));
+        assert!((&color - &lit_color).len() < 1e-9);
+
+        // A spot light only lights points inside of its cone
+        let spot = SpotLight::new(
+            1.0,
+            Vector3::new(0.0, 5.0, 3.0),
+            Vector3::new(0.0, -1.0, 0.0),
+            10.0,
+            20.0,
+        );
+        scene.lights = vec![Box::new(spot.clone())];
+        assert_eq!(
+            scene.compute_color(&ray, &hit, 0, &mut Rng::new(0)),
+            lit_color
+        );
+        scene.lights = vec![Box::new(SpotLight {
+            direction: Vector3::new(1.0, -1.0, 0.0).normalize(),
+            ..spot
+        })];
+        assert_eq!(
+            scene.compute_color(&ray, &hit, 0, &mut Rng::new(0)),
+            ambient_color
+        );
+    }
+
+    fn facing_mirrors() -> Scene {
+        // A red mirror at z = 5 facing a green one at z = 0, without any lights
+        let mut scene = Scene::new(Vec::new(), 0.1);
+        scene.add_shape(Plane::new(
+            Vector3::new(0.0, 0.0, 5.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            Material::mirror(Vector3::red(), 1.0),
+        ));
+        scene.add_shape(Plane::new(
+            Vector3::zero(),
+            Vector3::new(0.0, 0.0, 1.0),
+            Material::mirror(Vector3::green(), 1.0),
+        ));
+        scene
+    }
+
+    #[test]
+    fn test_facing_mirrors_terminate_at_max_depth() {
+        // The ray bounces back and forth, so the mirror seen at the last bounce
+        // depends on whether the maximum depth is even or odd
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 2.5), Vector3::new(0.0, 0.0, 1.0));
+        let mut scene = facing_mirrors();
 
-        let color = scene.compute_color(&ray, Vector3::new(0.0, 1.0, 3.0), &scene.spheres[0]);
+        scene.max_depth = 4;
+        assert_eq!(
+            scene.trace_color(&ray, 0, &mut Rng::new(0)),
+            &Vector3::red() * 0.1
+        );
+
+        scene.max_depth = 5;
+        assert_eq!(
+            scene.trace_color(&ray, 0, &mut Rng::new(0)),
+            &Vector3::green() * 0.1
+        );
 
+        scene.max_depth = 0;
         assert_eq!(
-            color,
-            &(&Vector3::red() * scene.ambient_light)
-                + &(&Vector3::red() * (scene.ambient_light * 2.8))
+            scene.trace_color(&ray, 0, &mut Rng::new(0)),
+            &Vector3::red() * 0.1
         );
     }
 
+    #[test]
+    fn test_partial_reflection_blends_colors() {
+        let mut scene = Scene::new(Vec::new(), 0.1);
+        scene.add_shape(Plane::new(
+            Vector3::zero(),
+            Vector3::new(0.0, 1.0, 0.0),
+            Material::mirror(Vector3::red(), 0.25),
+        ));
+        // The reflected ray misses everything and picks up the background
+        let ray = Ray::new(Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, -1.0, 1.0));
+        let expected =
+            &(&(&Vector3::red() * 0.1) * 0.75) + &(&Vector3::new(127.0, 127.0, 127.0) * 0.25);
+        assert_eq!(scene.trace_color(&ray, 0, &mut Rng::new(0)), expected);
+    }
+
+    #[test]
+    fn test_glass_sphere_transmission_and_absorption() {
+        // A ray through the center of a glass sphere hits both surfaces head on, where 4%
+        // of the light is reflected. With a maximum depth of two, the light reflected inside
+        // the sphere is cut off and only contributes the black local shading.
+        let absorption = 0.3;
+        let mut scene = Scene::new(Vec::new(), 0.1);
+        scene.add_shape(Sphere::new(
+            Vector3::new(0.0, 0.0, 5.0),
+            1.0,
+            Material::glass(1.5, Vector3::new(absorption, absorption, absorption)),
+        ));
+        scene.max_depth = 2;
+
+        let ray = Ray::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0));
+        let background = 127.0;
+        // Light leaving the back surface has traveled twice the radius inside the glass
+        let leaving = 0.96 * background * (-absorption * 2.0).exp();
+        let expected = 0.04 * background + 0.96 * leaving;
+        let color = scene.trace_color(&ray, 0, &mut Rng::new(0));
+        assert!((color.x - expected).abs() < 1e-9);
+        assert_eq!(color.x, color.y);
+        assert_eq!(color.x, color.z);
+    }
+
+    #[test]
+    fn test_trace_scene_returns_closest_hit() {
+        let mut scene = Scene::new(Vec::new(), 0.1);
+        scene.add_shape(Sphere::new(
+            Vector3::new(0.0, 0.0, 8.0),
+            1.0,
+            Material::new(Vector3::red()),
+        ));
+        scene.add_shape(Sphere::new(
+            Vector3::new(0.0, 0.0, 4.0),
+            1.0,
+            Material::new(Vector3::green()),
+        ));
+        let ray = Ray::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0));
+
+        let hit = scene.trace_scene(&ray).unwrap();
+        assert_eq!(hit.object_id, 1);
+        assert_eq!(hit.t, 3.0);
+        assert_eq!(hit.point, Vector3::new(0.0, 0.0, 3.0));
+        assert_eq!(hit.normal, Vector3::new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn test_trace_scene_with_sphere_on_ground_plane() {
+        let mut scene = Scene::new(Vec::new(), 0.1);
+        scene.add_shape(Plane::new(
+            Vector3::new(0.0, -1.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            Material::new(Vector3::green()),
+        ));
+        scene.add_shape(Sphere::new(
+            Vector3::new(0.0, 0.0, 4.0),
+            1.0,
+            Material::new(Vector3::red()),
+        ));
+
+        let ray = Ray::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(scene.trace_scene(&ray).unwrap().object_id, 1);
+
+        let ray = Ray::new(Vector3::zero(), Vector3::new(0.0, -1.0, 1.0));
+        let hit = scene.trace_scene(&ray).unwrap();
+        assert_eq!(hit.object_id, 0);
+        assert_eq!(hit.point, Vector3::new(0.0, -1.0, 1.0));
+    }
+
+    #[test]
+    fn test_trace_scene_with_mesh() {
+        let mut scene = Scene::new(Vec::new(), 0.1);
+        let positions = vec![
+            Vector3::new(-1.0, -1.0, 3.0),
+            Vector3::new(1.0, -1.0, 3.0),
+            Vector3::new(1.0, 1.0, 3.0),
+            Vector3::new(-1.0, 1.0, 3.0),
+        ];
+        scene.add_mesh(TriangleMesh::new(
+            positions,
+            vec![[0, 1, 2], [0, 2, 3]],
+            Material::new(Vector3::red()),
+        ));
+        assert_eq!(scene.shapes.len(), 2);
+
+        let ray = Ray::new(Vector3::zero(), Vector3::new(-0.1, 0.1, 1.0));
+        let hit = scene.trace_scene(&ray).unwrap();
+        assert_eq!(hit.object_id, 1);
+        assert_eq!(hit.t, 3.0);
+    }
+
+    #[test]
+    fn test_emissive_sphere_lights_the_scene() {
+        // A glowing sphere 2 units above a matte white floor, without any lights
+        let mut scene = Scene::new(Vec::new(), 0.1);
+        scene.add_shape(Plane::new(
+            Vector3::zero(),
+            Vector3::new(0.0, 1.0, 0.0),
+            Material::matte(Vector3::new(255.0, 255.0, 255.0)),
+        ));
+        scene.add_shape(Sphere::new(
+            Vector3::new(0.0, 2.0, 0.0),
+            0.5,
+            Material::emissive(Vector3::new(255.0, 255.0, 255.0)),
+        ));
+        assert_eq!(scene.emitters.len(), 1);
+        scene.build_bvh();
+
+        // The floor right below receives the radiance times the cross section over 2^2
+        let ray = Ray::new(Vector3::new(0.0, 1.0, -1.0), Vector3::new(0.0, -1.0, 1.0));
+        let color = scene.trace_color(&ray, 0, &mut Rng::new(0));
+        let expected = 25.5 + 255.0 * 1.7 * PI * 0.25 / 4.0;
+        assert!((color.x - expected).abs() < 0.02 * expected);
+        assert_eq!(color.x, color.z);
+
+        // Looking at the sphere shows its emission on top of the ambient light, which is
+        // zero for lamps
+        let ray = Ray::new(Vector3::new(0.0, 2.0, -5.0), Vector3::new(0.0, 0.0, 1.0));
+        let color = scene.trace_color(&ray, 0, &mut Rng::new(0));
+        assert_eq!(color, Vector3::new(255.0, 255.0, 255.0));
+    }
+
+    #[test]
+    fn test_emissive_mesh_is_a_single_light() {
+        let mut scene = Scene::new(Vec::new(), 0.1);
+        let positions = vec![
+            Vector3::new(-1.0, -1.0, 3.0),
+            Vector3::new(1.0, -1.0, 3.0),
+            Vector3::new(1.0, 1.0, 3.0),
+            Vector3::new(-1.0, 1.0, 3.0),
+        ];
+        scene.add_mesh(TriangleMesh::new(
+            positions.clone(),
+            vec![[0, 1, 2], [0, 2, 3]],
+            Material::new(Vector3::red()),
+        ));
+        assert!(scene.emitters.is_empty());
+        scene.add_mesh(TriangleMesh::new(
+            positions,
+            vec![[0, 1, 2], [0, 2, 3]],
+            Material::emissive(Vector3::new(0.0, 0.0, 255.0)),
+        ));
+        assert_eq!(scene.emitters.len(), 1);
+        assert_eq!(scene.shapes.len(), 4);
+
+        // The quad faces away from the origin, a point behind it is lit by the whole area
+        let samples =
+            scene.emitters[0].illuminate(&Vector3::new(0.0, 0.0, 1003.0), &mut Rng::new(0));
+        assert_eq!(samples.len(), 16);
+        let total: f64 = samples.iter().map(|sample| sample.intensity.z).sum();
+        assert!((total * 1000.0 * 1000.0 - 4.0).abs() < 1e-3);
+        assert!(scene.emitters[0]
+            .illuminate(&Vector3::zero(), &mut Rng::new(0))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_is_occluded_only_between_origin_and_target() {
+        let mut scene = Scene::new(Vec::new(), 0.1);
+        scene.add_shape(Sphere::new(
+            Vector3::new(0.0, 0.0, 5.0),
+            1.0,
+            Material::new(Vector3::red()),
+        ));
+
+        // The sphere lies between the points
+        assert!(scene.is_occluded(&Vector3::zero(), &Vector3::new(0.0, 0.0, 10.0)));
+        // The sphere lies beyond the target
+        assert!(!scene.is_occluded(&Vector3::zero(), &Vector3::new(0.0, 0.0, 3.0)));
+        // The sphere lies behind the origin
+        assert!(!scene.is_occluded(&Vector3::new(0.0, 0.0, 7.0), &Vector3::new(0.0, 0.0, 100.0)));
+        // A direction that is far from normalized must not change the result
+        assert!(!scene.is_occluded_along(&Vector3::zero(), &Vector3::new(0.0, 0.0, 0.01), 3.0));
+        assert!(scene.is_occluded_along(&Vector3::zero(), &Vector3::new(0.0, 0.0, 0.01), 4.5));
+    }
+
+    #[test]
+    fn test_compute_color_in_shadow_is_ambient() {
+        // A small sphere sits between the lit point and the light
+        let lights = vec![Light::new(1.2, Vector3::new(0.0, 7.0, 3.0))];
+        let mut scene = Scene::new(lights, 0.1);
+        scene.add_shape(Sphere::new(
+            Vector3::new(0.0, 0.0, 3.0),
+            1.0,
+            Material::new(Vector3::red()),
+        ));
+        scene.add_shape(Sphere::new(
+            Vector3::new(0.0, 4.0, 3.0),
+            0.5,
+            Material::new(Vector3::green()),
+        ));
+        scene.build_bvh();
+
+        let ray = Ray::new(Vector3::new(0.0, 5.0, 0.0), Vector3::new(0.0, -1.0, 1.0));
+        let hit = Intersection {
+            t: 3.0,
+            point: Vector3::new(0.0, 1.0, 3.0),
+            normal: Vector3::new(0.0, 1.0, 0.0),
+            uv: (0.0, 1.0),
+            object_id: 0,
+        };
+        let color = scene.compute_color(&ray, &hit, 0, &mut Rng::new(0));
+        assert_eq!(color, &Vector3::red() * scene.ambient_light);
+    }
+
+    fn random_point(rng: &mut Rng, extent: f64) -> Vector3 {
+        Vector3::new(
+            rng.range(-extent, extent),
+            rng.range(-extent, extent),
+            rng.range(-extent, extent),
+        )
+    }
+
+    fn random_scene(rng: &mut Rng) -> Scene {
+        let mut scene = Scene::new(Vec::new(), 0.1);
+        scene.add_shape(Plane::new(
+            Vector3::new(0.0, -12.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            Material::new(Vector3::green()),
+        ));
+        for _ in 0..150 {
+            let radius = rng.range(0.1, 1.5);
+            scene.add_shape(Sphere::new(
+                random_point(rng, 10.0),
+                radius,
+                Material::new(Vector3::red()),
+            ));
+        }
+        for _ in 0..150 {
+            let v0 = random_point(rng, 10.0);
+            let v1 = &v0 + &random_point(rng, 2.0);
+            let v2 = &v0 + &random_point(rng, 2.0);
+            scene.add_shape(Triangle::new(v0, v1, v2, Material::new(Vector3::purple())));
+        }
+        scene
+    }
+
+    #[test]
+    fn test_bvh_matches_brute_force() {
+        let mut rng = Rng::new(7);
+        for _ in 0..5 {
+            let mut scene = random_scene(&mut rng);
+            scene.build_bvh();
+            for _ in 0..2000 {
+                let origin = random_point(&mut rng, 15.0);
+                let direction = random_point(&mut rng, 1.0);
+                let ray = Ray::new(origin, direction);
+
+                let expected = scene.closest_hit_linear(&ray);
+                let actual = scene.trace_scene(&ray).map(|hit| (hit.object_id, hit.t));
+                assert_eq!(actual, expected);
+
+                let t_max = rng.range(0.0, 20.0);
+                let expected_any = scene
+                    .shapes
+                    .iter()
+                    .any(|shape| shape.intersect(&ray).is_some_and(|t| t < t_max));
+                assert_eq!(scene.intersects_any(&ray, t_max), expected_any);
+            }
+        }
+    }
 }
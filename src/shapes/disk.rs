@@ -0,0 +1,126 @@
+use math::{Vector3, AABB};
+use raytracing::{Material, Ray};
+use shapes::plane::intersect_plane;
+use shapes::Shape;
+use std::f64::consts::PI;
+
+// A flat, round disk around `origin` lying in the plane given by `normal`
+#[derive(Debug)]
+pub struct Disk {
+    pub origin: Vector3,
+    pub normal: Vector3,
+    pub radius: f64,
+    pub material: Material,
+}
+
+impl Disk {
+    pub fn new(origin: Vector3, normal: Vector3, radius: f64, material: Material) -> Disk {
+        Disk {
+            origin,
+            normal: normal.normalize(),
+            radius,
+            material,
+        }
+    }
+}
+
+impl Shape for Disk {
+    fn intersect(&self, ray: &Ray) -> Option<f64> {
+        // Intersect with the plane of the disk first, then check whether the hit point lies
+        // within the radius
+        let t = intersect_plane(ray, &self.origin, &self.normal)?;
+        let distance = (&ray.get_coordinates(t) - &self.origin).len();
+        if distance <= self.radius {
+            Some(t)
+        } else {
+            None
+        }
+    }
+
+    fn get_normal(&self, _p: &Vector3) -> Vector3 {
+        self.normal.clone()
+    }
+
+    fn get_uv(&self, p: &Vector3) -> (f64, f64) {
+        // Polar coordinates, u is the angle around the center and v the distance from it
+        let (tangent, bitangent) = self.normal.orthonormal_basis();
+        let local = p - &self.origin;
+        let angle = (&local % &bitangent).atan2(&local % &tangent);
+        let u = (angle + PI) / (2.0 * PI);
+        let v = (local.len() / self.radius).min(1.0);
+        (u, v)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        // The extent of a disk along an axis shrinks the more the normal is aligned with it
+        let n = &self.normal;
+        let extent = Vector3::new(
+            self.radius * (1.0 - n.x * n.x).max(0.0).sqrt(),
+            self.radius * (1.0 - n.y * n.y).max(0.0).sqrt(),
+            self.radius * (1.0 - n.z * n.z).max(0.0).sqrt(),
+        );
+        AABB::new(&self.origin - &extent, &self.origin + &extent)
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disk_intersection() {
+        let disk = Disk::new(
+            Vector3::new(0.0, 0.0, 3.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            1.0,
+            Material::new(Vector3::red()),
+        );
+        let ray = Ray::new(Vector3::new(0.5, 0.5, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(disk.intersect(&ray), Some(3.0));
+    }
+
+    #[test]
+    fn test_disk_intersection_returns_none_outside_radius() {
+        let disk = Disk::new(
+            Vector3::new(0.0, 0.0, 3.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            1.0,
+            Material::new(Vector3::red()),
+        );
+        // This ray hits the plane of the disk at a distance of sqrt(2) from the center
+        let ray = Ray::new(Vector3::new(1.0, 1.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(disk.intersect(&ray), None);
+    }
+
+    #[test]
+    fn test_disk_uv() {
+        let disk = Disk::new(
+            Vector3::zero(),
+            Vector3::new(0.0, 1.0, 0.0),
+            2.0,
+            Material::new(Vector3::red()),
+        );
+        let (_, v) = disk.get_uv(&Vector3::zero());
+        assert_eq!(v, 0.0);
+        let (u, v) = disk.get_uv(&Vector3::new(0.0, 0.0, 2.0));
+        assert!((0.0..=1.0).contains(&u));
+        assert!((v - 1.0).abs() < 1e-14);
+    }
+
+    #[test]
+    fn test_disk_bounding_box() {
+        let disk = Disk::new(
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            2.0,
+            Material::new(Vector3::red()),
+        );
+        let bbox = disk.bounding_box();
+        assert_eq!(bbox.min, Vector3::new(-2.0, 1.0, -2.0));
+        assert_eq!(bbox.max, Vector3::new(2.0, 1.0, 2.0));
+    }
+}
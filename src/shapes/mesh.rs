@@ -0,0 +1,214 @@
+use math::{Vector3, AABB};
+use raytracing::{Material, Ray};
+use shapes::triangle::{barycentric, intersect_triangle, triangle_bounding_box};
+use shapes::{Shape, Surface};
+use std::sync::Arc;
+
+// A triangle mesh with shared vertex buffers. Every entry of `indices` references three
+// vertices, which index into `positions` and, if present, into `normals` and `uvs`.
+#[derive(Debug)]
+pub struct TriangleMesh {
+    pub positions: Vec<Vector3>,
+    pub normals: Vec<Vector3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub indices: Vec<[usize; 3]>,
+    pub material: Material,
+    // Whether to interpolate the vertex normals across faces instead of using the face normal
+    pub smooth: bool,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Vector3>,
+        indices: Vec<[usize; 3]>,
+        material: Material,
+    ) -> TriangleMesh {
+        TriangleMesh {
+            positions,
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            indices,
+            material,
+            smooth: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn compute_vertex_normals(&mut self) {
+        // Averages the normals of all faces adjacent to a vertex, weighted by the face area.
+        // The unnormalized cross product is exactly twice the area of the face.
+        let mut normals = vec![Vector3::zero(); self.positions.len()];
+        for face in &self.indices {
+            let v0 = &self.positions[face[0]];
+            let face_normal =
+                (&self.positions[face[1]] - v0).cross(&(&self.positions[face[2]] - v0));
+            for &index in face {
+                normals[index] = &normals[index] + &face_normal;
+            }
+        }
+        self.normals = normals
+            .iter()
+            .map(|normal| {
+                if normal.len() > 0.0 {
+                    normal.normalize()
+                } else {
+                    Vector3::zero()
+                }
+            })
+            .collect();
+    }
+
+    fn vertices(&self, face: usize) -> (&Vector3, &Vector3, &Vector3) {
+        let [i0, i1, i2] = self.indices[face];
+        (
+            &self.positions[i0],
+            &self.positions[i1],
+            &self.positions[i2],
+        )
+    }
+}
+
+// A single face of a mesh, which is what the scene traces against
+#[derive(Debug)]
+pub struct MeshTriangle {
+    pub mesh: Arc<TriangleMesh>,
+    pub face: usize,
+}
+
+impl MeshTriangle {
+    pub fn new(mesh: Arc<TriangleMesh>, face: usize) -> MeshTriangle {
+        MeshTriangle { mesh, face }
+    }
+
+    pub fn from_mesh(mesh: TriangleMesh) -> Vec<MeshTriangle> {
+        let mesh = Arc::new(mesh);
+        (0..mesh.len())
+            .map(|face| MeshTriangle::new(mesh.clone(), face))
+            .collect()
+    }
+}
+
+impl Shape for MeshTriangle {
+    fn intersect(&self, ray: &Ray) -> Option<f64> {
+        let (v0, v1, v2) = self.mesh.vertices(self.face);
+        intersect_triangle(ray, v0, v1, v2).map(|(t, _, _)| t)
+    }
+
+    fn get_normal(&self, p: &Vector3) -> Vector3 {
+        let (v0, v1, v2) = self.mesh.vertices(self.face);
+        if self.mesh.smooth && !self.mesh.normals.is_empty() {
+            let (u, v) = barycentric(p, v0, v1, v2);
+            let [i0, i1, i2] = self.mesh.indices[self.face];
+            let normals = &self.mesh.normals;
+            let interpolated =
+                &(&(&normals[i0] * (1.0 - u - v)) + &(&normals[i1] * u)) + &(&normals[i2] * v);
+            interpolated.normalize()
+        } else {
+            (v1 - v0).cross(&(v2 - v0)).normalize()
+        }
+    }
+
+    fn get_uv(&self, p: &Vector3) -> (f64, f64) {
+        let (v0, v1, v2) = self.mesh.vertices(self.face);
+        let (u, v) = barycentric(p, v0, v1, v2);
+        if self.mesh.uvs.is_empty() {
+            return (u, v);
+        }
+        let [i0, i1, i2] = self.mesh.indices[self.face];
+        let uvs = &self.mesh.uvs;
+        let w = 1.0 - u - v;
+        (
+            uvs[i0].0 * w + uvs[i1].0 * u + uvs[i2].0 * v,
+            uvs[i0].1 * w + uvs[i1].1 * u + uvs[i2].1 * v,
+        )
+    }
+
+    fn bounding_box(&self) -> AABB {
+        let (v0, v1, v2) = self.mesh.vertices(self.face);
+        triangle_bounding_box(v0, v1, v2)
+    }
+
+    fn material(&self) -> &Material {
+        &self.mesh.material
+    }
+
+    fn surface(&self) -> Option<Surface> {
+        let (v0, v1, v2) = self.mesh.vertices(self.face);
+        Some(Surface::Triangle(v0.clone(), v1.clone(), v2.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A unit square at z = 2 made of two triangles
+    fn quad() -> TriangleMesh {
+        let positions = vec![
+            Vector3::new(0.0, 0.0, 2.0),
+            Vector3::new(1.0, 0.0, 2.0),
+            Vector3::new(1.0, 1.0, 2.0),
+            Vector3::new(0.0, 1.0, 2.0),
+        ];
+        let mut mesh = TriangleMesh::new(
+            positions,
+            vec![[0, 1, 2], [0, 2, 3]],
+            Material::new(Vector3::red()),
+        );
+        mesh.uvs = vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        mesh
+    }
+
+    #[test]
+    fn test_mesh_triangles_share_buffers() {
+        let triangles = MeshTriangle::from_mesh(quad());
+        assert_eq!(triangles.len(), 2);
+        assert!(Arc::ptr_eq(&triangles[0].mesh, &triangles[1].mesh));
+    }
+
+    #[test]
+    fn test_mesh_uv_interpolation() {
+        let triangles = MeshTriangle::from_mesh(quad());
+        let ray = Ray::new(Vector3::new(0.25, 0.75, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(triangles[0].intersect(&ray), None);
+        let t = triangles[1].intersect(&ray).unwrap();
+        let (u, v) = triangles[1].get_uv(&ray.get_coordinates(t));
+        assert!((u - 0.25).abs() < 1e-14);
+        assert!((v - 0.75).abs() < 1e-14);
+    }
+
+    #[test]
+    fn test_mesh_smooth_normals() {
+        // A tent of two faces meeting at the ridge x = 0
+        let positions = vec![
+            Vector3::new(-1.0, 0.0, 0.0),
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(0.0, 1.0, 1.0),
+            Vector3::new(1.0, 0.0, 0.0),
+        ];
+        let mut mesh = TriangleMesh::new(
+            positions,
+            vec![[0, 1, 2], [1, 3, 2]],
+            Material::new(Vector3::red()),
+        );
+        mesh.compute_vertex_normals();
+        mesh.smooth = true;
+        let triangles = MeshTriangle::from_mesh(mesh);
+
+        // On the ridge the interpolated normal points straight up the z axis
+        let normal = triangles[0].get_normal(&Vector3::new(0.0, 0.5, 1.0));
+        assert!((normal.z - 1.0).abs() < 1e-14);
+
+        // At the outer vertex it is the face normal
+        let normal = triangles[0].get_normal(&Vector3::new(-1.0, 0.0, 0.0));
+        let expected = Vector3::new(-1.0, 0.0, 1.0).normalize();
+        assert!((&normal - &expected).len() < 1e-14);
+    }
+}
@@ -1,3 +1,13 @@
+mod disk;
+mod mesh;
+mod plane;
+mod shape;
 mod sphere;
+mod triangle;
 
-pub use self::sphere::Sphere;
+pub use self::disk::Disk;
+pub use self::mesh::{MeshTriangle, TriangleMesh};
+pub use self::plane::{intersect_plane, Plane};
+pub use self::shape::{Shape, Surface};
+pub use self::sphere::{intersect_sphere, Sphere};
+pub use self::triangle::Triangle;
@@ -0,0 +1,133 @@
+use math::{Vector3, AABB};
+use raytracing::{Material, Ray};
+use shapes::Shape;
+use std::f64;
+
+// An infinite plane through `origin` with the given surface normal
+#[derive(Debug)]
+pub struct Plane {
+    pub origin: Vector3,
+    pub normal: Vector3,
+    pub material: Material,
+}
+
+impl Plane {
+    pub fn new(origin: Vector3, normal: Vector3, material: Material) -> Plane {
+        Plane {
+            origin,
+            normal: normal.normalize(),
+            material,
+        }
+    }
+}
+
+// Returns the ray parameter t at which the ray hits the plane through origin with the given
+// normal. Rays parallel to the plane never hit it.
+pub fn intersect_plane(ray: &Ray, origin: &Vector3, normal: &Vector3) -> Option<f64> {
+    let denominator = normal % &ray.direction;
+    if denominator.abs() < 1e-12 {
+        return None;
+    }
+
+    let t = &(origin - &ray.origin) % normal / denominator;
+    if t < 0.00001 {
+        None
+    } else {
+        Some(t)
+    }
+}
+
+impl Shape for Plane {
+    fn intersect(&self, ray: &Ray) -> Option<f64> {
+        intersect_plane(ray, &self.origin, &self.normal)
+    }
+
+    fn get_normal(&self, _p: &Vector3) -> Vector3 {
+        self.normal.clone()
+    }
+
+    fn get_uv(&self, p: &Vector3) -> (f64, f64) {
+        // The plane is tiled with textures of unit size along two tangent directions
+        let (tangent, bitangent) = self.normal.orthonormal_basis();
+        let local = p - &self.origin;
+        let u = &local % &tangent;
+        let v = &local % &bitangent;
+        (u - u.floor(), v - v.floor())
+    }
+
+    fn bounding_box(&self) -> AABB {
+        AABB::new(
+            Vector3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            Vector3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+        )
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plane_intersection() {
+        let plane = Plane::new(
+            Vector3::new(0.0, -1.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            Material::new(Vector3::red()),
+        );
+        let ray = Ray::new(Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, -1.0, 1.0));
+        assert_eq!(plane.intersect(&ray), Some(2.0));
+    }
+
+    #[test]
+    fn test_plane_intersection_returns_none_when_parallel() {
+        let plane = Plane::new(
+            Vector3::zero(),
+            Vector3::new(0.0, 1.0, 0.0),
+            Material::new(Vector3::red()),
+        );
+        let ray = Ray::new(Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(plane.intersect(&ray), None);
+    }
+
+    #[test]
+    fn test_plane_intersection_returns_none_behind_origin() {
+        let plane = Plane::new(
+            Vector3::zero(),
+            Vector3::new(0.0, 1.0, 0.0),
+            Material::new(Vector3::red()),
+        );
+        let ray = Ray::new(Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(plane.intersect(&ray), None);
+    }
+
+    #[test]
+    fn test_plane_normal_is_normalized() {
+        let plane = Plane::new(
+            Vector3::zero(),
+            Vector3::new(0.0, 3.0, 0.0),
+            Material::new(Vector3::red()),
+        );
+        assert_eq!(
+            plane.get_normal(&Vector3::new(4.0, 0.0, 2.0)),
+            Vector3::new(0.0, 1.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn test_plane_uv_is_periodic() {
+        let plane = Plane::new(
+            Vector3::zero(),
+            Vector3::new(0.0, 1.0, 0.0),
+            Material::new(Vector3::red()),
+        );
+        let (u1, v1) = plane.get_uv(&Vector3::new(0.25, 0.0, 0.75));
+        let (u2, v2) = plane.get_uv(&Vector3::new(3.25, 0.0, -1.25));
+        assert!((0.0..1.0).contains(&u1) && (0.0..1.0).contains(&v1));
+        assert!((u1 - u2).abs() < 1e-12);
+        assert!((v1 - v2).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,33 @@
+use math::{Vector3, AABB};
+use raytracing::{Material, Ray};
+
+// The geometry of a shape that can be sampled, which is what emissive shapes light the
+// scene with
+#[derive(Debug, Clone, PartialEq)]
+pub enum Surface {
+    Sphere { center: Vector3, radius: f64 },
+    // The vertices in counter-clockwise order seen from the front
+    Triangle(Vector3, Vector3, Vector3),
+}
+
+// Shapes are shared between the render threads and therefore have to be Send and Sync
+pub trait Shape: Send + Sync {
+    // Returns the ray parameter t of the closest intersection in front of the ray's origin
+    fn intersect(&self, ray: &Ray) -> Option<f64>;
+
+    // Returns the normalized surface normal at point p, which is assumed to lie on the shape
+    fn get_normal(&self, p: &Vector3) -> Vector3;
+
+    // Returns the texture coordinates in [0, 1] x [0, 1] of point p on the shape
+    fn get_uv(&self, p: &Vector3) -> (f64, f64);
+
+    fn bounding_box(&self) -> AABB;
+
+    fn material(&self) -> &Material;
+
+    // Returns the surface of shapes that can be sampled. Unbounded shapes like planes have
+    // none, so they can glow but don't light the scene.
+    fn surface(&self) -> Option<Surface> {
+        None
+    }
+}
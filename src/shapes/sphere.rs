@@ -1,79 +1,119 @@
-use math::Vector3;
-use raytracing::Ray;
+use math::{Vector3, AABB};
+use raytracing::{Material, Ray};
+use shapes::{Shape, Surface};
+use std::f64::consts::PI;
 
 #[derive(Debug)]
 pub struct Sphere {
     pub origin: Vector3,
     pub radius: f64,
-    pub color: Vector3,
+    pub material: Material,
 }
 
 impl Sphere {
-    pub fn new(origin: Vector3, radius: f64, color: Vector3) -> Sphere {
+    pub fn new(origin: Vector3, radius: f64, material: Material) -> Sphere {
         Sphere {
             origin,
             radius,
-            color,
+            material,
         }
     }
 
-    pub fn new_default_color(origin: Vector3, radius: f64) -> Sphere {
+    pub fn new_default_material(origin: Vector3, radius: f64) -> Sphere {
         Sphere {
             origin,
             radius,
-            color: Vector3::new(255.0, 255.0, 255.0),
+            material: Material::default(),
         }
     }
+}
 
-    pub fn get_normal(&self, p: &Vector3) -> Vector3 {
-        (p - &self.origin).normalize()
+// Returns the ray parameter t of the closest intersection of the ray with the sphere around
+// origin that lies in front of the ray's origin
+pub fn intersect_sphere(ray: &Ray, origin: &Vector3, radius: f64) -> Option<f64> {
+    // From http://ambrsoft.com/TrigoCalc/Sphere/SpherLineIntersection_.htm
+    let x1 = ray.origin.x;
+    let x2 = ray.direction.x + ray.origin.x;
+    let x3 = origin.x;
+
+    let y1 = ray.origin.y;
+    let y2 = ray.direction.y + ray.origin.y;
+    let y3 = origin.y;
+
+    let z1 = ray.origin.z;
+    let z2 = ray.direction.z + ray.origin.z;
+    let z3 = origin.z;
+
+    let a = (x2 - x1).powi(2) + (y2 - y1).powi(2) + (z2 - z1).powi(2);
+    let b = 2.0 * ((x2 - x1) * (x1 - x3) + (y2 - y1) * (y1 - y3) + (z2 - z1) * (z1 - z3));
+    let c = x3.powi(2) + y3.powi(2) + z3.powi(2) + x1.powi(2) + y1.powi(2) + z1.powi(2)
+        - 2.0 * (x3 * x1 + y3 * y1 + z3 * z1)
+        - radius.powi(2);
+
+    let discr = b.powi(2) - 4.0 * a * c;
+
+    if discr < 0.0 {
+        // no intersection
+        None
+    } else if discr > 0.0 {
+        let t_plus = (-b + discr.sqrt()) / (2.0 * a);
+        let t_minus = (-b - discr.sqrt()) / (2.0 * a);
+        let (t_near, t_far) = if t_plus < t_minus {
+            (t_plus, t_minus)
+        } else {
+            (t_minus, t_plus)
+        };
+
+        // Return the one thats closer to the rays origin, which is the smaller t.
+        // If the ray starts inside the sphere only the far one lies in front of it.
+        if t_near >= 0.00001 {
+            Some(t_near)
+        } else if t_far >= 0.00001 {
+            Some(t_far)
+        } else {
+            None
+        }
+    } else {
+        let t_result = -b / (2.0 * a);
+        if t_result < 0.00001 {
+            None
+        } else {
+            Some(t_result)
+        }
     }
+}
 
-    pub fn intersect(&self, ray: &Ray) -> Option<f64> {
-        // From http://ambrsoft.com/TrigoCalc/Sphere/SpherLineIntersection_.htm
-        let x1 = ray.origin.x;
-        let x2 = ray.direction.x + ray.origin.x;
-        let x3 = self.origin.x;
-
-        let y1 = ray.origin.y;
-        let y2 = ray.direction.y + ray.origin.y;
-        let y3 = self.origin.y;
-
-        let z1 = ray.origin.z;
-        let z2 = ray.direction.z + ray.origin.z;
-        let z3 = self.origin.z;
-
-        let a = (x2 - x1).powi(2) + (y2 - y1).powi(2) + (z2 - z1).powi(2);
-        let b = 2.0 * ((x2 - x1) * (x1 - x3) + (y2 - y1) * (y1 - y3) + (z2 - z1) * (z1 - z3));
-        let c = x3.powi(2) + y3.powi(2) + z3.powi(2) + x1.powi(2) + y1.powi(2) + z1.powi(2)
-            - 2.0 * (x3 * x1 + y3 * y1 + z3 * z1)
-            - self.radius.powi(2);
+impl Shape for Sphere {
+    fn intersect(&self, ray: &Ray) -> Option<f64> {
+        intersect_sphere(ray, &self.origin, self.radius)
+    }
 
-        let discr = b.powi(2) - 4.0 * a * c;
+    fn get_normal(&self, p: &Vector3) -> Vector3 {
+        (p - &self.origin).normalize()
+    }
 
-        if discr < 0.0 {
-            // no intersection
-            None
-        } else if discr > 0.0 {
-            let t_plus = (-b + discr.sqrt()) / (2.0 * a);
-            let t_minus = (-b - discr.sqrt()) / (2.0 * a);
+    fn get_uv(&self, p: &Vector3) -> (f64, f64) {
+        // Spherical coordinates of the point, the azimuth maps to u and the polar angle to v
+        let n = self.get_normal(p);
+        let u = 0.5 + n.z.atan2(n.x) / (2.0 * PI);
+        let v = 0.5 + n.y.clamp(-1.0, 1.0).asin() / PI;
+        (u, v)
+    }
 
-            // Return the one thats closer to the rays origin, which is the smaller t
-            let t_result = if t_plus < t_minus { t_plus } else { t_minus };
+    fn bounding_box(&self) -> AABB {
+        let extent = Vector3::new(self.radius, self.radius, self.radius);
+        AABB::new(&self.origin - &extent, &self.origin + &extent)
+    }
 
-            if t_result < 0.00001 {
-                return None;
-            }
+    fn material(&self) -> &Material {
+        &self.material
+    }
 
-            Some(t_result)
-        } else {
-            let t_result = -b / (2.0 * a);
-            if t_result < 0.00001 {
-                return None;
-            } else {
-                return Some(t_result);
-            }
-        }
+    fn surface(&self) -> Option<Surface> {
+        Some(Surface::Sphere {
+            center: self.origin.clone(),
+            radius: self.radius,
+        })
     }
 }
 
@@ -84,7 +124,7 @@ mod tests {
 
     #[test]
     fn test_sphere_intersection() {
-        let sp = Sphere::new_default_color(Vector3::new(0.0, 0.0, 3.0), 2.0);
+        let sp = Sphere::new_default_material(Vector3::new(0.0, 0.0, 3.0), 2.0);
         let ray = Ray::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0));
         if let Some(t_result) = sp.intersect(&ray) {
             assert_eq!(t_result, 1.0);
@@ -101,7 +141,7 @@ mod tests {
         // v = -s*sin(angle) + t*cos(angle)
         // The intersection should then be at height 2 * sin(pi/4) = 1.41...
         // The x value has the same value except shifted by 3
-        let sp = Sphere::new_default_color(Vector3::new(0.0, 0.0, 3.0), 2.0);
+        let sp = Sphere::new_default_material(Vector3::new(0.0, 0.0, 3.0), 2.0);
         let ray = Ray::new(
             Vector3::new(0.0, 2.0 * (consts::PI / 4.0).sin(), 0.0),
             Vector3::new(0.0, 0.0, 1.0),
@@ -113,16 +153,41 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_sphere_intersection_from_inside() {
+        // Rays starting inside or on the surface, like refracted rays, hit the far side
+        let sp = Sphere::new_default_material(Vector3::new(0.0, 0.0, 3.0), 2.0);
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 3.0), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(sp.intersect(&ray), Some(2.0));
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(sp.intersect(&ray), Some(4.0));
+    }
+
     #[test]
     fn test_get_normal() {
-        let sp = Sphere::new_default_color(Vector3::new(0.0, 0.0, 2.0), 2.0);
+        let sp = Sphere::new_default_material(Vector3::new(0.0, 0.0, 2.0), 2.0);
         let normal = sp.get_normal(&Vector3::new(0.0, 2.0, 2.0));
         assert_eq!(normal, Vector3::new(0.0, 1.0, 0.0));
     }
 
+    #[test]
+    fn test_get_uv() {
+        let sp = Sphere::new_default_material(Vector3::new(0.0, 0.0, 2.0), 1.0);
+        // The north pole maps to the top edge, a point on the equator to the middle
+        let (_, v) = sp.get_uv(&Vector3::new(0.0, 1.0, 2.0));
+        assert!((v - 1.0).abs() < 1e-14);
+        let (u, v) = sp.get_uv(&Vector3::new(-1.0, 0.0, 2.0));
+        assert!((u - 1.0).abs() < 1e-14);
+        assert!((v - 0.5).abs() < 1e-14);
+    }
+
     #[test]
     fn test_sphere_intersection_returns_none_on_miss() {
-        let sphere = Sphere::new(Vector3::new(0.0, 0.0, 3.0), 1.0, Vector3::red());
+        let sphere = Sphere::new(
+            Vector3::new(0.0, 0.0, 3.0),
+            1.0,
+            Material::new(Vector3::red()),
+        );
         let ray = Ray::new(Vector3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, 1.0));
         let result = sphere.intersect(&ray);
         assert_eq!(result, None);
@@ -131,7 +196,11 @@ mod tests {
     #[test]
     fn test_sphere_intersection_returns_none_on_miss_2() {
         // This ray starts at the top of the sphere
-        let sphere = Sphere::new(Vector3::new(0.0, 0.0, 3.0), 1.0, Vector3::red());
+        let sphere = Sphere::new(
+            Vector3::new(0.0, 0.0, 3.0),
+            1.0,
+            Material::new(Vector3::red()),
+        );
         let ray = Ray::new(Vector3::new(0.0, 1.0, 3.0), Vector3::new(0.0, 0.0, 1.0));
         let result = sphere.intersect(&ray);
         assert_eq!(result, None);
@@ -140,7 +209,11 @@ mod tests {
     #[test]
     fn test_sphere_intersection_returns_none_on_miss_3() {
         // This ray starts above the sphere
-        let sphere = Sphere::new(Vector3::new(0.0, 0.0, 3.0), 1.0, Vector3::red());
+        let sphere = Sphere::new(
+            Vector3::new(0.0, 0.0, 3.0),
+            1.0,
+            Material::new(Vector3::red()),
+        );
         let ray = Ray::new(Vector3::new(0.0, 2.0, 3.0), Vector3::new(0.0, 0.0, 1.0));
         let result = sphere.intersect(&ray);
         assert_eq!(result, None);
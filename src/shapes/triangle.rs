@@ -0,0 +1,186 @@
+use math::{Vector3, AABB};
+use raytracing::{Material, Ray};
+use shapes::{Shape, Surface};
+
+#[derive(Debug)]
+pub struct Triangle {
+    pub v0: Vector3,
+    pub v1: Vector3,
+    pub v2: Vector3,
+    pub material: Material,
+}
+
+impl Triangle {
+    pub fn new(v0: Vector3, v1: Vector3, v2: Vector3, material: Material) -> Triangle {
+        Triangle {
+            v0,
+            v1,
+            v2,
+            material,
+        }
+    }
+}
+
+// Intersects the ray with the triangle v0, v1, v2 and returns the ray parameter t together with
+// the barycentric coordinates u and v, which are the weights of v1 and v2 respectively.
+// From https://en.wikipedia.org/wiki/M%C3%B6ller%E2%80%93Trumbore_intersection_algorithm
+pub fn intersect_triangle(
+    ray: &Ray,
+    v0: &Vector3,
+    v1: &Vector3,
+    v2: &Vector3,
+) -> Option<(f64, f64, f64)> {
+    let edge1 = v1 - v0;
+    let edge2 = v2 - v0;
+    let p = ray.direction.cross(&edge2);
+    let determinant = &edge1 % &p;
+
+    // The ray is parallel to the plane of the triangle
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    let inverse_determinant = 1.0 / determinant;
+
+    let s = &ray.origin - v0;
+    let u = (&s % &p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(&edge1);
+    let v = (&ray.direction % &q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = (&edge2 % &q) * inverse_determinant;
+    if t < 0.00001 {
+        None
+    } else {
+        Some((t, u, v))
+    }
+}
+
+// Returns the barycentric coordinates u and v of point p with respect to the triangle v0, v1, v2,
+// using the same convention as intersect_triangle
+pub fn barycentric(p: &Vector3, v0: &Vector3, v1: &Vector3, v2: &Vector3) -> (f64, f64) {
+    let edge1 = v1 - v0;
+    let edge2 = v2 - v0;
+    let to_point = p - v0;
+    let d11 = &edge1 % &edge1;
+    let d12 = &edge1 % &edge2;
+    let d22 = &edge2 % &edge2;
+    let dp1 = &to_point % &edge1;
+    let dp2 = &to_point % &edge2;
+    let denominator = d11 * d22 - d12 * d12;
+    let u = (d22 * dp1 - d12 * dp2) / denominator;
+    let v = (d11 * dp2 - d12 * dp1) / denominator;
+    (u, v)
+}
+
+pub fn triangle_bounding_box(v0: &Vector3, v1: &Vector3, v2: &Vector3) -> AABB {
+    AABB::new(
+        Vector3::new(
+            v0.x.min(v1.x).min(v2.x),
+            v0.y.min(v1.y).min(v2.y),
+            v0.z.min(v1.z).min(v2.z),
+        ),
+        Vector3::new(
+            v0.x.max(v1.x).max(v2.x),
+            v0.y.max(v1.y).max(v2.y),
+            v0.z.max(v1.z).max(v2.z),
+        ),
+    )
+}
+
+impl Shape for Triangle {
+    fn intersect(&self, ray: &Ray) -> Option<f64> {
+        intersect_triangle(ray, &self.v0, &self.v1, &self.v2).map(|(t, _, _)| t)
+    }
+
+    fn get_normal(&self, _p: &Vector3) -> Vector3 {
+        // Counter-clockwise winding defines the front face
+        (&self.v1 - &self.v0)
+            .cross(&(&self.v2 - &self.v0))
+            .normalize()
+    }
+
+    fn get_uv(&self, p: &Vector3) -> (f64, f64) {
+        // Without texture coordinates we use the barycentric coordinates
+        barycentric(p, &self.v0, &self.v1, &self.v2)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        triangle_bounding_box(&self.v0, &self.v1, &self.v2)
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn surface(&self) -> Option<Surface> {
+        Some(Surface::Triangle(
+            self.v0.clone(),
+            self.v1.clone(),
+            self.v2.clone(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_triangle() -> Triangle {
+        Triangle::new(
+            Vector3::new(0.0, 0.0, 2.0),
+            Vector3::new(1.0, 0.0, 2.0),
+            Vector3::new(0.0, 1.0, 2.0),
+            Material::new(Vector3::red()),
+        )
+    }
+
+    #[test]
+    fn test_triangle_intersection() {
+        let triangle = unit_triangle();
+        let ray = Ray::new(Vector3::new(0.25, 0.5, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        let (t, u, v) = intersect_triangle(&ray, &triangle.v0, &triangle.v1, &triangle.v2).unwrap();
+        assert_eq!(t, 2.0);
+        assert_eq!(u, 0.25);
+        assert_eq!(v, 0.5);
+    }
+
+    #[test]
+    fn test_triangle_intersection_returns_none_on_miss() {
+        let triangle = unit_triangle();
+        // This ray passes the hypotenuse
+        let ray = Ray::new(Vector3::new(0.75, 0.75, 0.0), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(triangle.intersect(&ray), None);
+    }
+
+    #[test]
+    fn test_triangle_intersection_returns_none_when_parallel() {
+        let triangle = unit_triangle();
+        let ray = Ray::new(Vector3::new(-1.0, 0.25, 2.0), Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(triangle.intersect(&ray), None);
+    }
+
+    #[test]
+    fn test_barycentric_matches_intersection() {
+        let triangle = unit_triangle();
+        let ray = Ray::new(Vector3::new(0.1, 0.3, -1.0), Vector3::new(0.0, 0.0, 1.0));
+        let (t, u, v) = intersect_triangle(&ray, &triangle.v0, &triangle.v1, &triangle.v2).unwrap();
+        let (bu, bv) = triangle.get_uv(&ray.get_coordinates(t));
+        assert!((u - bu).abs() < 1e-14);
+        assert!((v - bv).abs() < 1e-14);
+    }
+
+    #[test]
+    fn test_triangle_normal() {
+        let triangle = unit_triangle();
+        assert_eq!(
+            triangle.get_normal(&triangle.v0),
+            Vector3::new(0.0, 0.0, 1.0)
+        );
+    }
+}
@@ -0,0 +1,566 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use util::framebuffer::{Framebuffer, LinearRGB};
+use util::image_error::ImageError;
+
+// Reads and writes uncompressed scanline OpenEXR files. Each image is stored as one layer with
+// R, G and B channels, where the channels of every layer but the unnamed first one are
+// prefixed with the layer name like "diffuse.R". Other compression methods and tiled
+// files are not supported by the reader.
+// See https://openexr.com/en/latest/OpenEXRFileLayout.html
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const VERSION: u32 = 2;
+const LONG_NAMES: u32 = 0x400;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Precision {
+    Half,
+    Float,
+}
+
+impl Precision {
+    fn pixel_type(self) -> i32 {
+        match self {
+            Precision::Half => 1,
+            Precision::Float => 2,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Precision::Half => 2,
+            Precision::Float => 4,
+        }
+    }
+}
+
+pub fn get_bytes(layers: &[(&str, &Framebuffer)], precision: Precision) -> Result<Vec<u8>, String> {
+    let (width, height) = match layers.first() {
+        Some(&(_, framebuffer)) => (framebuffer.width(), framebuffer.height()),
+        None => return Err(invalid("there are no layers to write")),
+    };
+    for &(name, framebuffer) in layers {
+        if (framebuffer.width(), framebuffer.height()) != (width, height) {
+            return Err(invalid(&format!(
+                "layer \"{}\" differs in size from the first layer",
+                name
+            )));
+        }
+    }
+
+    // Channels have to be sorted by name, each one points to the layer and color it holds
+    let mut channels = BTreeMap::new();
+    for (index, &(name, _)) in layers.iter().enumerate() {
+        for (component, channel) in ["R", "G", "B"].iter().enumerate() {
+            let channel_name = if name.is_empty() {
+                channel.to_string()
+            } else {
+                format!("{}.{}", name, channel)
+            };
+            channels.insert(channel_name, (index, component));
+        }
+    }
+
+    let mut channel_list = Vec::new();
+    for name in channels.keys() {
+        channel_list.extend_from_slice(name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&precision.pixel_type().to_le_bytes());
+        // pLinear and three reserved bytes, followed by the sampling rates
+        channel_list.extend_from_slice(&[0, 0, 0, 0]);
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channel_list.push(0);
+
+    let mut window = Vec::new();
+    for value in &[0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+
+    let mut header = Vec::new();
+    let mut long_names = false;
+    {
+        let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+            long_names |= name.len() > 31;
+            header.extend_from_slice(name.as_bytes());
+            header.push(0);
+            header.extend_from_slice(kind.as_bytes());
+            header.push(0);
+            header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+            header.extend_from_slice(value);
+        };
+        attribute("channels", "chlist", &channel_list);
+        attribute("compression", "compression", &[0]);
+        attribute("dataWindow", "box2i", &window);
+        attribute("displayWindow", "box2i", &window);
+        attribute("lineOrder", "lineOrder", &[0]);
+        attribute("pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+        attribute("screenWindowCenter", "v2f", &[0; 8]);
+        attribute("screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    }
+    long_names |= channels.keys().any(|name| name.len() > 31);
+    header.push(0);
+
+    let mut bytes = MAGIC.to_vec();
+    let version = if long_names {
+        VERSION | LONG_NAMES
+    } else {
+        VERSION
+    };
+    bytes.extend_from_slice(&version.to_le_bytes());
+    bytes.extend_from_slice(&header);
+
+    // Every scanline is a chunk of its own, the offset table points to the start of each
+    let line_size = width as usize * channels.len() * precision.size();
+    let table_end = bytes.len() + height as usize * 8;
+    for y in 0..height as usize {
+        let offset = (table_end + y * (8 + line_size)) as u64;
+        bytes.extend_from_slice(&offset.to_le_bytes());
+    }
+    for y in 0..height {
+        bytes.extend_from_slice(&(y as i32).to_le_bytes());
+        bytes.extend_from_slice(&(line_size as i32).to_le_bytes());
+        for &(index, component) in channels.values() {
+            let framebuffer = layers[index].1;
+            for x in 0..width {
+                let pixel = framebuffer.get_pixel(x, y).expect("Pixel inside the image");
+                let value = [pixel.r, pixel.g, pixel.b][component];
+                match precision {
+                    Precision::Half => bytes.extend_from_slice(&f32_to_half(value).to_le_bytes()),
+                    Precision::Float => bytes.extend_from_slice(&value.to_le_bytes()),
+                }
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+pub fn write_file(
+    layers: &[(&str, &Framebuffer)],
+    precision: Precision,
+    filename: &str,
+) -> Result<(), ImageError> {
+    let bytes = get_bytes(layers, precision)
+        .map_err(|message| ImageError::Encoding(filename.to_string(), message))?;
+    fs::write(Path::new(filename), bytes).map_err(|err| ImageError::Io(filename.to_string(), err))
+}
+
+struct Channel {
+    name: String,
+    pixel_type: i32,
+}
+
+pub fn parse(bytes: &[u8], file: &str) -> Result<Vec<(String, Framebuffer)>, ImageError> {
+    decode(bytes).map_err(|message| ImageError::Decoding(file.to_string(), message))
+}
+
+fn decode(bytes: &[u8]) -> Result<Vec<(String, Framebuffer)>, String> {
+    let mut reader = Reader { bytes, position: 0 };
+    if reader.take(4)? != MAGIC {
+        return Err(invalid("not an OpenEXR file"));
+    }
+    let version = reader.u32()?;
+    if version & 0xff != VERSION {
+        return Err(invalid("unsupported version"));
+    }
+    if version & !(0xff | LONG_NAMES) != 0 {
+        return Err(invalid("only single part scanline files are supported"));
+    }
+
+    let mut channels = Vec::new();
+    let mut window = None;
+    loop {
+        let name = reader.string()?;
+        if name.is_empty() {
+            break;
+        }
+        let kind = reader.string()?;
+        let size = reader.u32()? as usize;
+        let value = reader.take(size)?;
+        let mut value = Reader {
+            bytes: value,
+            position: 0,
+        };
+        match (name.as_str(), kind.as_str()) {
+            ("channels", "chlist") => loop {
+                let name = value.string()?;
+                if name.is_empty() {
+                    break;
+                }
+                let pixel_type = value.u32()? as i32;
+                value.take(4)?;
+                if value.u32()? != 1 || value.u32()? != 1 {
+                    return Err(invalid("subsampled channels are not supported"));
+                }
+                if pixel_type != 1 && pixel_type != 2 {
+                    return Err(invalid("only half and float channels are supported"));
+                }
+                channels.push(Channel { name, pixel_type });
+            },
+            ("compression", "compression") if value.take(1)?[0] != 0 => {
+                return Err(invalid("only uncompressed files are supported"));
+            }
+            ("dataWindow", "box2i") => {
+                let mut coordinates = [0; 4];
+                for coordinate in &mut coordinates {
+                    *coordinate = value.u32()? as i32;
+                }
+                window = Some(coordinates);
+            }
+            _ => {}
+        }
+    }
+
+    let window = window.ok_or_else(|| invalid("missing dataWindow"))?;
+    let width = window_size(window[0], window[2])?;
+    let height = window_size(window[1], window[3])?;
+
+    // Every scanline has an offset, its y coordinate and size, and a value per channel and
+    // pixel. The file has to hold all of them before any layer is allocated.
+    let pixel_size: usize = channels
+        .iter()
+        .map(|channel| if channel.pixel_type == 1 { 2 } else { 4 })
+        .sum();
+    let data_size = (width as usize)
+        .checked_mul(pixel_size)
+        .and_then(|line_size| line_size.checked_add(16))
+        .and_then(|line_size| line_size.checked_mul(height as usize))
+        .ok_or_else(|| invalid("the image is too large"))?;
+    if data_size > bytes.len() - reader.position {
+        return Err(invalid("unexpected end of the file"));
+    }
+
+    // Group the channels into layers by their prefix
+    let mut layers: Vec<(String, Framebuffer)> = Vec::new();
+    let mut targets = Vec::new();
+    for channel in &channels {
+        let (layer, color) = match channel.name.rfind('.') {
+            Some(dot) => (&channel.name[..dot], &channel.name[dot + 1..]),
+            None => ("", channel.name.as_str()),
+        };
+        let component = match color {
+            "R" => Some(0),
+            "G" => Some(1),
+            "B" => Some(2),
+            // A single luminance channel is shown as gray
+            "Y" => Some(3),
+            _ => None,
+        };
+        let index = match layers.iter().position(|(name, _)| name == layer) {
+            Some(index) => index,
+            None => {
+                layers.push((layer.to_string(), Framebuffer::new(width, height)));
+                layers.len() - 1
+            }
+        };
+        targets.push(component.map(|component| (index, component)));
+    }
+
+    for _ in 0..height {
+        reader.u64()?;
+    }
+    for _ in 0..height {
+        let y = (reader.u32()? as i32).checked_sub(window[1]);
+        let size = reader.u32()? as usize;
+        let y = match y {
+            Some(y) if y >= 0 && (y as u32) < height => y as u32,
+            _ => return Err(invalid("scanline outside of the dataWindow")),
+        };
+        let mut line = Reader {
+            bytes: reader.take(size)?,
+            position: 0,
+        };
+        for (channel, target) in channels.iter().zip(&targets) {
+            for x in 0..width {
+                let value = if channel.pixel_type == 1 {
+                    half_to_f32(line.u16()?)
+                } else {
+                    f32::from_bits(line.u32()?)
+                };
+                if let Some((index, component)) = *target {
+                    let framebuffer = &mut layers[index].1;
+                    let mut pixel = framebuffer.get_pixel(x, y).expect("Inside the image");
+                    match component {
+                        0 => pixel.r = value,
+                        1 => pixel.g = value,
+                        2 => pixel.b = value,
+                        _ => pixel = LinearRGB::new(value, value, value),
+                    }
+                    framebuffer.set_pixel(x, y, pixel);
+                }
+            }
+        }
+    }
+    Ok(layers)
+}
+
+pub fn read_file(filename: &str) -> Result<Vec<(String, Framebuffer)>, ImageError> {
+    let bytes =
+        fs::read(Path::new(filename)).map_err(|err| ImageError::Io(filename.to_string(), err))?;
+    parse(&bytes, filename)
+}
+
+fn window_size(min: i32, max: i32) -> Result<u32, String> {
+    // Both bounds are part of the window, which is empty when max is min - 1
+    match max.checked_sub(min).and_then(|size| size.checked_add(1)) {
+        Some(size) if size >= 0 => Ok(size as u32),
+        _ => Err(invalid("invalid dataWindow")),
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.position + count > self.bytes.len() {
+            return Err(invalid("unexpected end of the file"));
+        }
+        self.position += count;
+        Ok(&self.bytes[self.position - count..self.position])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let low = u64::from(self.u32()?);
+        let high = u64::from(self.u32()?);
+        Ok(high << 32 | low)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let start = self.position;
+        while self.take(1)?[0] != 0 {}
+        Ok(String::from_utf8_lossy(&self.bytes[start..self.position - 1]).into_owned())
+    }
+}
+
+fn invalid(message: &str) -> String {
+    message.to_string()
+}
+
+pub fn f32_to_half(value: f32) -> u16 {
+    // Converts to a 16 bit float, rounding to the nearest representable value
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x007f_ffff;
+
+    if exponent == 0xff {
+        // Infinity stays infinity and NaN stays NaN
+        let nan = if mantissa != 0 { 0x0200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        // Too large, becomes infinity
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        // Too small for a normal half, becomes a subnormal or zero
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - exponent) as u32;
+        let half = mantissa >> shift;
+        let remainder = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round = remainder > halfway || (remainder == halfway && half & 1 == 1);
+        return sign | (half as u16 + round as u16);
+    }
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    let remainder = mantissa & 0x1fff;
+    let round = remainder > 0x1000 || (remainder == 0x1000 && half & 1 == 1);
+    // Rounding up may carry into the exponent, which correctly gives the next power of two
+    // or infinity
+    sign | (half + round as u32) as u16
+}
+
+pub fn half_to_f32(half: u16) -> f32 {
+    let sign = u32::from(half & 0x8000) << 16;
+    let exponent = u32::from((half >> 10) & 0x1f);
+    let mantissa = u32::from(half & 0x03ff);
+    let bits = match exponent {
+        0 if mantissa == 0 => sign,
+        0 => {
+            // Subnormal, normalize it for the larger exponent range of f32
+            let shift = mantissa.leading_zeros() - 21;
+            let mantissa = (mantissa << shift) & 0x03ff;
+            sign | ((127 - 15 + 1 - shift) << 23) | (mantissa << 13)
+        }
+        0x1f => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn test_image(seed: f32) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(4, 3);
+        for y in 0..3 {
+            for x in 0..4 {
+                let value = seed + (x + 4 * y) as f32;
+                framebuffer.set_pixel(x, y, LinearRGB::new(value, value * 0.5, -value * 100.0));
+            }
+        }
+        framebuffer
+    }
+
+    #[test]
+    fn test_half_conversion() {
+        for &(value, half) in &[
+            (0.0f32, 0x0000u16),
+            (-0.0, 0x8000),
+            (1.0, 0x3c00),
+            (-2.0, 0xc000),
+            (0.5, 0x3800),
+            (65504.0, 0x7bff),
+            (6.103_515_6e-5, 0x0400),
+            (5.960_464_5e-8, 0x0001),
+        ] {
+            assert_eq!(f32_to_half(value), half, "{}", value);
+            assert_eq!(half_to_f32(half), value);
+        }
+        assert_eq!(f32_to_half(1e6), 0x7c00);
+        assert_eq!(f32_to_half(1e-9), 0);
+        assert!(half_to_f32(f32_to_half(f32::NAN)).is_nan());
+        // 1 + 2^-11 lies exactly between two halves and rounds to the even one
+        assert_eq!(f32_to_half(1.0 + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(f32_to_half(1.0 + 3.0 * 2f32.powi(-11)), 0x3c02);
+        // Every half survives the round trip
+        for half in 0..0x7c00u16 {
+            assert_eq!(f32_to_half(half_to_f32(half)), half);
+        }
+    }
+
+    #[test]
+    fn test_float_round_trip() {
+        let image = test_image(0.25);
+        let layers = parse(
+            &get_bytes(&[("", &image)], Precision::Float).unwrap(),
+            "test.exr",
+        )
+        .unwrap();
+        assert_eq!(layers, vec![(String::new(), image)]);
+    }
+
+    #[test]
+    fn test_half_round_trip() {
+        let image = test_image(0.25);
+        let layers = parse(
+            &get_bytes(&[("", &image)], Precision::Half).unwrap(),
+            "test.exr",
+        )
+        .unwrap();
+        assert_eq!(layers.len(), 1);
+        for (expected, actual) in image.pixels().iter().zip(layers[0].1.pixels()) {
+            assert!((expected.r - actual.r).abs() <= expected.r.abs() / 1000.0);
+            assert!((expected.g - actual.g).abs() <= expected.g.abs() / 1000.0);
+            assert!((expected.b - actual.b).abs() <= expected.b.abs() / 1000.0);
+        }
+    }
+
+    #[test]
+    fn test_multiple_layers() {
+        let beauty = test_image(0.0);
+        let diffuse = test_image(100.0);
+        let name = "a_layer_name_that_is_longer_than_thirty_one_characters";
+        let bytes = get_bytes(&[("", &beauty), (name, &diffuse)], Precision::Float).unwrap();
+        assert_eq!(bytes[5] & 0x04, 0x04);
+        let mut layers = parse(&bytes, "test.exr").unwrap();
+        layers.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            layers,
+            vec![(String::new(), beauty), (name.to_string(), diffuse)]
+        );
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let path = env::temp_dir().join(format!("rusttracer-{}.exr", std::process::id()));
+        let path = path.to_str().unwrap();
+        let image = test_image(0.5);
+        write_file(&[("", &image)], Precision::Float, path).unwrap();
+        let result = read_file(path);
+        fs::remove_file(path).unwrap();
+        assert_eq!(result.unwrap(), vec![(String::new(), image)]);
+    }
+
+    #[test]
+    fn test_layers_must_match_in_size() {
+        let path = env::temp_dir().join(format!("rusttracer-layers-{}.exr", std::process::id()));
+        let path = path.to_str().unwrap();
+        let small = Framebuffer::new(2, 2);
+        match write_file(
+            &[("", &test_image(0.0)), ("small", &small)],
+            Precision::Half,
+            path,
+        ) {
+            Err(ImageError::Encoding(file, _)) => assert_eq!(file, path),
+            result => panic!("expected an encoding error, got {:?}", result),
+        }
+        match write_file(&[], Precision::Half, path) {
+            Err(ImageError::Encoding(file, _)) => assert_eq!(file, path),
+            result => panic!("expected an encoding error, got {:?}", result),
+        }
+        assert!(fs::metadata(path).is_err());
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let bytes = get_bytes(&[("", &test_image(0.0))], Precision::Half).unwrap();
+        assert!(parse(&bytes[..bytes.len() - 1], "test.exr").is_err());
+        assert!(parse(b"PF\n1 1\n-1.0\n", "test.exr").is_err());
+        let mut compressed = bytes.clone();
+        let position = compressed
+            .windows(12)
+            .position(|window| window == b"compression\0")
+            .unwrap();
+        // The value follows the type name and the size
+        compressed[position + 12 + 12 + 4] = 3;
+        assert!(parse(&compressed, "test.exr").is_err());
+    }
+
+    #[test]
+    fn test_invalid_data_window() {
+        let bytes = get_bytes(&[("", &test_image(0.0))], Precision::Half).unwrap();
+        let position = bytes
+            .windows(11)
+            .position(|window| window == b"dataWindow\0")
+            .unwrap();
+        // The coordinates follow the type name and the size
+        let start = position + 11 + 6 + 4;
+        let with_window = |window: [i32; 4]| {
+            let mut bytes = bytes.clone();
+            for (i, coordinate) in window.iter().enumerate() {
+                bytes[start + 4 * i..start + 4 * i + 4].copy_from_slice(&coordinate.to_le_bytes());
+            }
+            bytes
+        };
+        assert!(parse(&with_window([0, 0, 3, 2]), "test.exr").is_ok());
+        // Sizes that overflow, are negative or don't fit the file
+        assert!(parse(&with_window([i32::MIN, 0, 0, 1]), "test.exr").is_err());
+        assert!(parse(&with_window([0, 0, -2, 2]), "test.exr").is_err());
+        assert!(parse(&with_window([0, 0, i32::MAX - 1, i32::MAX - 1]), "test.exr").is_err());
+        // Scanlines whose y coordinate overflows when moved into the window
+        assert!(parse(&with_window([0, i32::MIN, 3, i32::MIN + 2]), "test.exr").is_err());
+    }
+}
@@ -0,0 +1,203 @@
+// An image in memory holding linear radiance as floats, where 1.0 is the brightest value a
+// regular 8 bit image can show. Brighter values are kept until the image is quantized
+// for output. Pixels are stored row by row starting with the top row, so the pixel
+// at x = 0, y = 0 is the top left corner of the image.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RGB {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl RGB {
+    pub fn new(r: u8, g: u8, b: u8) -> RGB {
+        RGB { r, g, b }
+    }
+
+    pub fn black() -> RGB {
+        RGB::new(0, 0, 0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinearRGB {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+impl LinearRGB {
+    pub fn new(r: f32, g: f32, b: f32) -> LinearRGB {
+        LinearRGB { r, g, b }
+    }
+
+    pub fn black() -> LinearRGB {
+        LinearRGB::new(0.0, 0.0, 0.0)
+    }
+
+    pub fn from_rgb(color: RGB) -> LinearRGB {
+        LinearRGB::new(
+            f32::from(color.r) / 255.0,
+            f32::from(color.g) / 255.0,
+            f32::from(color.b) / 255.0,
+        )
+    }
+
+    pub fn to_rgb(self) -> RGB {
+        // Quantizes to 8 bits, everything outside of [0, 1] is clipped
+        RGB::new(quantize(self.r), quantize(self.g), quantize(self.b))
+    }
+}
+
+fn quantize(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<LinearRGB>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Framebuffer {
+        // Constructs a black image of the given size
+        Framebuffer::filled(width, height, LinearRGB::black())
+    }
+
+    pub fn filled(width: u32, height: u32, color: LinearRGB) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![color; width as usize * height as usize],
+        }
+    }
+
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<LinearRGB>) -> Option<Framebuffer> {
+        // Constructs an image from its pixels given row by row from the top,
+        // returns None if their number does not match the size
+        if pixels.len() == width as usize * height as usize {
+            Some(Framebuffer {
+                width,
+                height,
+                pixels,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn index(&self, x: u32, y: u32) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y as usize * self.width as usize + x as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<LinearRGB> {
+        self.index(x, y).map(|index| self.pixels[index])
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: LinearRGB) -> bool {
+        match self.index(x, y) {
+            Some(index) => {
+                self.pixels[index] = color;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn pixels(&self) -> &[LinearRGB] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [LinearRGB] {
+        &mut self.pixels
+    }
+
+    pub fn to_rgb_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 3);
+        for pixel in &self.pixels {
+            let rgb = pixel.to_rgb();
+            bytes.extend_from_slice(&[rgb.r, rgb.g, rgb.b]);
+        }
+        bytes
+    }
+
+    pub fn to_rgba_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 4);
+        for pixel in &self.pixels {
+            let rgb = pixel.to_rgb();
+            bytes.extend_from_slice(&[rgb.r, rgb.g, rgb.b, 255]);
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size_is_width_then_height() {
+        let mut framebuffer = Framebuffer::new(4, 2);
+        assert_eq!((framebuffer.width(), framebuffer.height()), (4, 2));
+        let color = LinearRGB::new(0.1, 0.2, 0.3);
+        assert!(framebuffer.set_pixel(3, 1, color));
+        assert!(!framebuffer.set_pixel(1, 3, color));
+        assert_eq!(framebuffer.get_pixel(3, 1), Some(color));
+        assert_eq!(framebuffer.get_pixel(4, 0), None);
+        // The last pixel in memory is the bottom right one
+        assert_eq!(framebuffer.pixels()[7], color);
+    }
+
+    #[test]
+    fn test_from_pixels_is_row_major() {
+        let pixels = (0..6)
+            .map(|i| LinearRGB::from_rgb(RGB::new(i, 0, 0)))
+            .collect();
+        let framebuffer = Framebuffer::from_pixels(3, 2, pixels).unwrap();
+        assert_eq!(
+            framebuffer.get_pixel(2, 0).unwrap().to_rgb(),
+            RGB::new(2, 0, 0)
+        );
+        assert_eq!(
+            framebuffer.get_pixel(0, 1).unwrap().to_rgb(),
+            RGB::new(3, 0, 0)
+        );
+        assert_eq!(
+            framebuffer.to_rgba_bytes()[..8].to_vec(),
+            vec![0, 0, 0, 255, 1, 0, 0, 255]
+        );
+        assert!(Framebuffer::from_pixels(2, 2, vec![LinearRGB::black(); 3]).is_none());
+    }
+
+    #[test]
+    fn test_keeps_high_dynamic_range_until_output() {
+        let mut framebuffer = Framebuffer::new(2, 1);
+        framebuffer.set_pixel(0, 0, LinearRGB::new(4.0, 0.5, -1.0));
+        framebuffer.set_pixel(1, 0, LinearRGB::new(1.0, 0.25, 0.0));
+        assert_eq!(
+            framebuffer.get_pixel(0, 0),
+            Some(LinearRGB::new(4.0, 0.5, -1.0))
+        );
+
+        // Scaling brings the highlight back into range
+        for pixel in framebuffer.pixels_mut() {
+            pixel.r *= 0.25;
+        }
+        assert_eq!(framebuffer.to_rgb_bytes(), vec![255, 128, 0, 64, 64, 0]);
+    }
+}
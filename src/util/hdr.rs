@@ -0,0 +1,330 @@
+use std::fs;
+use std::path::Path;
+use util::framebuffer::{Framebuffer, LinearRGB};
+use util::image_error::ImageError;
+
+// Reads and writes Radiance .hdr files. Every pixel is stored as RGBE, three 8 bit mantissas
+// sharing an 8 bit exponent. Scanlines are written with the run length encoding of newer
+// Radiance versions, the reader also understands uncompressed files.
+// See https://radiance-online.org/cgi-bin/viewcvs.cgi/ray/src/common/color.c
+
+pub fn get_bytes(framebuffer: &Framebuffer) -> Vec<u8> {
+    let width = framebuffer.width() as usize;
+    let mut bytes = format!(
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        framebuffer.height(),
+        width
+    )
+    .into_bytes();
+    if width == 0 {
+        return bytes;
+    }
+    for row in framebuffer.pixels().chunks(width) {
+        let scanline: Vec<[u8; 4]> = row.iter().map(|&pixel| to_rgbe(pixel)).collect();
+        if (8..0x8000).contains(&width) {
+            write_rle_scanline(&scanline, &mut bytes);
+        } else {
+            // Run length encoding is only defined for these widths
+            for rgbe in scanline {
+                bytes.extend_from_slice(&rgbe);
+            }
+        }
+    }
+    bytes
+}
+
+pub fn write_file(framebuffer: &Framebuffer, filename: &str) -> Result<(), ImageError> {
+    fs::write(Path::new(filename), get_bytes(framebuffer))
+        .map_err(|err| ImageError::Io(filename.to_string(), err))
+}
+
+pub fn parse(bytes: &[u8], file: &str) -> Result<Framebuffer, ImageError> {
+    decode(bytes).map_err(|message| ImageError::Decoding(file.to_string(), message))
+}
+
+fn decode(bytes: &[u8]) -> Result<Framebuffer, String> {
+    let mut position = 0;
+    let read_line = |position: &mut usize| -> Result<String, String> {
+        let start = *position;
+        while *position < bytes.len() && bytes[*position] != b'\n' {
+            *position += 1;
+        }
+        if *position == bytes.len() {
+            return Err(invalid("unexpected end of the header"));
+        }
+        *position += 1;
+        Ok(String::from_utf8_lossy(&bytes[start..*position - 1]).into_owned())
+    };
+
+    let magic = read_line(&mut position)?;
+    if magic != "#?RADIANCE" && magic != "#?RGBE" {
+        return Err(invalid("not a Radiance HDR file"));
+    }
+    loop {
+        let line = read_line(&mut position)?;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid(&format!("unsupported {}", line)));
+        }
+    }
+    let resolution = read_line(&mut position)?;
+    let fields: Vec<&str> = resolution.split_whitespace().collect();
+    let (height, width) = match fields.as_slice() {
+        ["-Y", height, "+X", width] => match (height.parse(), width.parse()) {
+            (Ok(height), Ok(width)) => (height, width),
+            _ => return Err(invalid("invalid resolution")),
+        },
+        _ => {
+            return Err(invalid(&format!(
+                "unsupported resolution line {}, only -Y height +X width is supported",
+                resolution
+            )))
+        }
+    };
+
+    let pixel_count = (width as usize)
+        .checked_mul(height as usize)
+        .ok_or_else(|| invalid("the image is too large"))?;
+    if pixel_count == 0 {
+        return Ok(Framebuffer::new(width, height));
+    }
+    // Runs pack many pixels into a few bytes, so only what the rest of the file holds without
+    // compression is reserved up front
+    let mut pixels = Vec::with_capacity(pixel_count.min((bytes.len() - position) / 4));
+    for _ in 0..height {
+        let scanline = read_scanline(bytes, &mut position, width as usize)?;
+        pixels.extend(scanline.into_iter().map(from_rgbe));
+    }
+    Ok(Framebuffer::from_pixels(width, height, pixels).expect("Size matches the pixels"))
+}
+
+pub fn read_file(filename: &str) -> Result<Framebuffer, ImageError> {
+    let bytes =
+        fs::read(Path::new(filename)).map_err(|err| ImageError::Io(filename.to_string(), err))?;
+    parse(&bytes, filename)
+}
+
+fn invalid(message: &str) -> String {
+    message.to_string()
+}
+
+fn to_rgbe(pixel: LinearRGB) -> [u8; 4] {
+    let brightest = pixel.r.max(pixel.g).max(pixel.b);
+    if brightest.is_nan() || brightest < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    // Split the brightest channel into a mantissa in [0.5, 1) and an exponent
+    let mut exponent = brightest.log2().floor() as i32 + 1;
+    if brightest / (exponent as f32).exp2() >= 1.0 {
+        exponent += 1;
+    }
+    let exponent = exponent.clamp(-128, 127);
+    let scale = 256.0 / (exponent as f32).exp2();
+    let mantissa = |value: f32| (value.max(0.0) * scale).min(255.0) as u8;
+    [
+        mantissa(pixel.r),
+        mantissa(pixel.g),
+        mantissa(pixel.b),
+        (exponent + 128) as u8,
+    ]
+}
+
+fn from_rgbe(rgbe: [u8; 4]) -> LinearRGB {
+    if rgbe[3] == 0 {
+        return LinearRGB::black();
+    }
+    let scale = (i32::from(rgbe[3]) - (128 + 8)) as f32;
+    let scale = scale.exp2();
+    LinearRGB::new(
+        (f32::from(rgbe[0]) + 0.5) * scale,
+        (f32::from(rgbe[1]) + 0.5) * scale,
+        (f32::from(rgbe[2]) + 0.5) * scale,
+    )
+}
+
+fn write_rle_scanline(scanline: &[[u8; 4]], bytes: &mut Vec<u8>) {
+    let width = scanline.len();
+    bytes.extend_from_slice(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8]);
+    // Each of the four components is encoded separately, as runs of equal values and
+    // literal stretches, both at most 127 long
+    for component in 0..4 {
+        let values: Vec<u8> = scanline.iter().map(|rgbe| rgbe[component]).collect();
+        let mut start = 0;
+        while start < width {
+            let mut run = 1;
+            while start + run < width && run < 127 && values[start + run] == values[start] {
+                run += 1;
+            }
+            if run >= 4 {
+                bytes.extend_from_slice(&[128 + run as u8, values[start]]);
+                start += run;
+                continue;
+            }
+            // Collect literals until the next run of at least four values starts
+            let mut end = start;
+            while end < width && end - start < 127 {
+                if end + 3 < width
+                    && values[end] == values[end + 1]
+                    && values[end] == values[end + 2]
+                    && values[end] == values[end + 3]
+                {
+                    break;
+                }
+                end += 1;
+            }
+            bytes.push((end - start) as u8);
+            bytes.extend_from_slice(&values[start..end]);
+            start = end;
+        }
+    }
+}
+
+fn read_scanline(bytes: &[u8], position: &mut usize, width: usize) -> Result<Vec<[u8; 4]>, String> {
+    let take = |position: &mut usize, count: usize| -> Result<&[u8], String> {
+        if *position + count > bytes.len() {
+            return Err(invalid("unexpected end of the pixel data"));
+        }
+        *position += count;
+        Ok(&bytes[*position - count..*position])
+    };
+
+    let start = *position;
+    let header = take(position, 4.min(bytes.len() - start))?;
+    let is_rle = (8..0x8000).contains(&width)
+        && header.len() == 4
+        && header[0] == 2
+        && header[1] == 2
+        && header[2] & 0x80 == 0;
+    if !is_rle {
+        // Uncompressed pixels, one RGBE quadruple after the other
+        *position = start;
+        let size = width
+            .checked_mul(4)
+            .ok_or_else(|| invalid("the image is too large"))?;
+        let data = take(position, size)?;
+        return Ok(data
+            .chunks(4)
+            .map(|rgbe| [rgbe[0], rgbe[1], rgbe[2], rgbe[3]])
+            .collect());
+    }
+    if (usize::from(header[2]) << 8 | usize::from(header[3])) != width {
+        return Err(invalid("scanline width does not match the image width"));
+    }
+
+    let mut scanline = vec![[0; 4]; width];
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = take(position, 1)?[0] as usize;
+            if count > 128 {
+                let count = count - 128;
+                let value = take(position, 1)?[0];
+                if x + count > width {
+                    return Err(invalid("run exceeds the scanline"));
+                }
+                for pixel in &mut scanline[x..x + count] {
+                    pixel[component] = value;
+                }
+                x += count;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err(invalid("invalid run length"));
+                }
+                let values = take(position, count)?;
+                for (pixel, &value) in scanline[x..x + count].iter_mut().zip(values) {
+                    pixel[component] = value;
+                }
+                x += count;
+            }
+        }
+    }
+    Ok(scanline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn test_image(width: u32, height: u32) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                // A flat area for runs, with a gradient and very bright pixels in it
+                let color = if x < width / 2 {
+                    LinearRGB::new(0.25, 0.5, 0.75)
+                } else {
+                    LinearRGB::new(x as f32 * 0.3, y as f32 * 10.0, 1000.0 / (x + 1) as f32)
+                };
+                framebuffer.set_pixel(x, y, color);
+            }
+        }
+        framebuffer
+    }
+
+    fn assert_similar(expected: &Framebuffer, actual: &Framebuffer) {
+        // RGBE keeps 8 bits of precision relative to the brightest channel of each pixel
+        assert_eq!(
+            (expected.width(), expected.height()),
+            (actual.width(), actual.height())
+        );
+        for (e, a) in expected.pixels().iter().zip(actual.pixels()) {
+            let tolerance = e.r.max(e.g).max(e.b) / 100.0;
+            assert!((e.r - a.r).abs() <= tolerance, "{:?} != {:?}", e, a);
+            assert!((e.g - a.g).abs() <= tolerance, "{:?} != {:?}", e, a);
+            assert!((e.b - a.b).abs() <= tolerance, "{:?} != {:?}", e, a);
+        }
+    }
+
+    #[test]
+    fn test_rgbe() {
+        assert_eq!(to_rgbe(LinearRGB::black()), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe(LinearRGB::new(1.0, 0.5, 0.0)), [128, 64, 0, 129]);
+        assert_eq!(from_rgbe([128, 64, 0, 129]).r, 1.0 + 0.5 / 128.0);
+        assert_eq!(from_rgbe([0, 0, 0, 0]), LinearRGB::black());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Narrow images are stored flat, wider ones run length encoded
+        for &(width, height) in &[(5, 3), (200, 4)] {
+            let image = test_image(width, height);
+            assert_similar(&image, &parse(&get_bytes(&image), "test.hdr").unwrap());
+        }
+    }
+
+    #[test]
+    fn test_run_length_encoding_compresses_flat_areas() {
+        let image = Framebuffer::filled(1000, 2, LinearRGB::new(0.1, 0.2, 0.3));
+        let bytes = get_bytes(&image);
+        assert!(bytes.len() < 200);
+        assert_similar(&image, &parse(&bytes, "test.hdr").unwrap());
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let path = env::temp_dir().join(format!("rusttracer-{}.hdr", std::process::id()));
+        let path = path.to_str().unwrap();
+        let image = test_image(64, 8);
+        write_file(&image, path).unwrap();
+        let result = read_file(path);
+        fs::remove_file(path).unwrap();
+        assert_similar(&image, &result.unwrap());
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        assert!(parse(b"P6 1 1 255\n", "test.hdr").is_err());
+        assert!(parse(b"#?RADIANCE\n\n-Y 2 +X 2\n\x01\x02", "test.hdr").is_err());
+        assert!(parse(b"#?RADIANCE\n\n+X 2 -Y 2\n", "test.hdr").is_err());
+        // A header claiming a huge image in a tiny file
+        assert!(parse(
+            b"#?RADIANCE\n\n-Y 4000000000 +X 4000000000\n\x01\x02\x03\x04",
+            "test.hdr"
+        )
+        .is_err());
+        assert!(parse(b"#?RADIANCE\n\n-Y 4000000000 +X 1\n", "test.hdr").is_err());
+    }
+}
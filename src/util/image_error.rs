@@ -0,0 +1,40 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+// The error of reading or writing an image file, each variant holds the name of the file
+#[derive(Debug)]
+pub enum ImageError {
+    Io(String, io::Error),
+    // The image could not be encoded in the requested format
+    Encoding(String, String),
+    // The file is damaged or uses features of the format we do not support
+    Decoding(String, String),
+    UnsupportedFormat(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::Io(file, err) => write!(f, "{}: {}", file, err),
+            ImageError::Encoding(file, message) => {
+                write!(f, "{}: could not encode image: {}", file, message)
+            }
+            ImageError::Decoding(file, message) => write!(f, "{}: {}", file, message),
+            ImageError::UnsupportedFormat(file) => write!(
+                f,
+                "{}: unsupported image format, expected one of png, ppm, hdr, pfm or exr",
+                file
+            ),
+        }
+    }
+}
+
+impl Error for ImageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ImageError::Io(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
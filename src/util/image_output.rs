@@ -1,21 +1,265 @@
 extern crate png;
 
 // For reading and opening files
-use std::path::Path;
 use std::fs::File;
-use std::io::BufWriter;
+use std::io::{BufWriter, Write};
+use std::path::Path;
 // To use encoder.set()
 use self::png::HasParameters;
+use util::exr::{self, Precision};
+use util::framebuffer::Framebuffer;
+use util::image_error::ImageError;
+use util::tonemap::ToneMapping;
+use util::{hdr, pfm, ppm};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Ppm,
+    // Plain text P3, which is never guessed from the extension
+    PlainPpm,
+    Hdr,
+    Pfm,
+    Exr,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &str) -> Result<ImageFormat, ImageError> {
+        // Guesses the format from the file extension
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+        match extension.as_deref() {
+            Some("png") => Ok(ImageFormat::Png),
+            Some("ppm") => Ok(ImageFormat::Ppm),
+            Some("hdr") => Ok(ImageFormat::Hdr),
+            Some("pfm") => Ok(ImageFormat::Pfm),
+            Some("exr") => Ok(ImageFormat::Exr),
+            _ => Err(ImageError::UnsupportedFormat(path.to_string())),
+        }
+    }
+
+    pub fn is_high_dynamic_range(self) -> bool {
+        // These formats store the radiance as is, without tone mapping
+        match self {
+            ImageFormat::Png | ImageFormat::Ppm | ImageFormat::PlainPpm => false,
+            ImageFormat::Hdr | ImageFormat::Pfm | ImageFormat::Exr => true,
+        }
+    }
+}
 
-pub fn write_png_img(rgba_sequence: &Vec<u8>, width: u32, height: u32, path: String) {
+pub fn write_image(
+    framebuffer: &Framebuffer,
+    format: ImageFormat,
+    tone_mapping: &ToneMapping,
+    precision: Precision,
+    path: &str,
+) -> Result<(), ImageError> {
+    match format {
+        ImageFormat::Png => {
+            let image = tone_mapping.apply(framebuffer);
+            write_png_img(
+                &image.to_rgba_bytes(),
+                image.width(),
+                image.height(),
+                path.to_string(),
+            )
+        }
+        ImageFormat::Ppm => ppm::write_file(&tone_mapping.apply(framebuffer), path),
+        ImageFormat::PlainPpm => ppm::write_ascii_file(&tone_mapping.apply(framebuffer), path),
+        ImageFormat::Hdr => hdr::write_file(framebuffer, path),
+        ImageFormat::Pfm => pfm::write_file(framebuffer, path),
+        ImageFormat::Exr => exr::write_file(&[("", framebuffer)], precision, path),
+    }
+}
 
-    let path = Path::new(&path);
-    let file = File::create(path).unwrap();
-    let ref mut w = BufWriter::new(file);
+pub fn write_png_img(
+    rgba_sequence: &[u8],
+    width: u32,
+    height: u32,
+    path: String,
+) -> Result<(), ImageError> {
+    let file = File::create(Path::new(&path)).map_err(|err| ImageError::Io(path.clone(), err))?;
+    encode_png(BufWriter::new(file), rgba_sequence, width, height, &path)
+}
 
-    let mut encoder = png::Encoder::new(w, width, height); // Width is 2 pixels and height is 1.
+fn encode_png<W: Write>(
+    mut w: W,
+    rgba_sequence: &[u8],
+    width: u32,
+    height: u32,
+    path: &str,
+) -> Result<(), ImageError> {
+    let png_error = |err| match err {
+        png::EncodingError::IoError(err) => ImageError::Io(path.to_string(), err),
+        png::EncodingError::Format(message) => {
+            ImageError::Encoding(path.to_string(), message.into())
+        }
+    };
+
+    let mut encoder = png::Encoder::new(&mut w, width, height);
     encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
-    let mut writer = encoder.write_header().unwrap();
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    writer.write_image_data(rgba_sequence).map_err(png_error)?;
+    // The writer adds the end chunk when it is dropped and ignores any error doing so. Flushing
+    // afterwards reports whatever could not be written.
+    drop(writer);
+    w.flush()
+        .map_err(|err| ImageError::Io(path.to_string(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io;
+    use util::framebuffer::LinearRGB;
+
+    #[test]
+    fn test_format_from_extension() {
+        let format = |path| ImageFormat::from_path(path).ok();
+        assert_eq!(format("out.png"), Some(ImageFormat::Png));
+        assert_eq!(format("a/b.c/out.EXR"), Some(ImageFormat::Exr));
+        assert_eq!(format("render.hdr"), Some(ImageFormat::Hdr));
+        assert_eq!(format("render.pfm"), Some(ImageFormat::Pfm));
+        assert_eq!(format("render.ppm"), Some(ImageFormat::Ppm));
+        match ImageFormat::from_path("render.jpg") {
+            Err(ImageError::UnsupportedFormat(file)) => assert_eq!(file, "render.jpg"),
+            _ => panic!("Expected an unsupported format"),
+        }
+        assert!(format("render").is_none());
+    }
+
+    #[test]
+    fn test_high_dynamic_range_formats_keep_radiance() {
+        let mut framebuffer = Framebuffer::new(2, 2);
+        framebuffer.set_pixel(1, 0, LinearRGB::new(8.0, 0.5, 0.25));
+        let tone_mapping = ToneMapping::default();
+        let directory = env::temp_dir();
+        let path = |extension: &str| {
+            let name = format!("rusttracer-output-{}.{}", std::process::id(), extension);
+            directory.join(name).to_str().unwrap().to_string()
+        };
+
+        let pfm_path = path("pfm");
+        write_image(
+            &framebuffer,
+            ImageFormat::Pfm,
+            &tone_mapping,
+            Precision::Half,
+            &pfm_path,
+        )
+        .unwrap();
+        let pfm = pfm::read_file(&pfm_path);
+        fs::remove_file(&pfm_path).unwrap();
+        assert_eq!(pfm.unwrap(), framebuffer);
+
+        let exr_path = path("exr");
+        write_image(
+            &framebuffer,
+            ImageFormat::Exr,
+            &tone_mapping,
+            Precision::Half,
+            &exr_path,
+        )
+        .unwrap();
+        let exr = exr::read_file(&exr_path);
+        fs::remove_file(&exr_path).unwrap();
+        assert_eq!(exr.unwrap(), vec![(String::new(), framebuffer.clone())]);
+
+        // PPM is tone mapped, so the bright pixel is clipped
+        let ppm_path = path("ppm");
+        write_image(
+            &framebuffer,
+            ImageFormat::Ppm,
+            &tone_mapping,
+            Precision::Half,
+            &ppm_path,
+        )
+        .unwrap();
+        let ppm = fs::read(&ppm_path);
+        fs::remove_file(&ppm_path).unwrap();
+        let ppm = ppm.unwrap();
+        assert_eq!(&ppm[ppm.len() - 9..ppm.len() - 6], &[255, 188, 137]);
+    }
+
+    #[test]
+    fn test_unwritable_paths_return_errors() {
+        let framebuffer = Framebuffer::new(2, 2);
+        let tone_mapping = ToneMapping::default();
+        let missing_directory = env::temp_dir().join("rusttracer-does-not-exist");
+        let directory = env::temp_dir();
+        for extension in &["png", "ppm", "hdr", "pfm", "exr"] {
+            // Neither a file in a directory that does not exist nor a directory can be written
+            let missing = missing_directory.join(format!("out.{}", extension));
+            let missing = missing.to_str().unwrap();
+            let existing = directory.join(format!("rusttracer-directory.{}", extension));
+            let existing = existing.to_str().unwrap().to_string();
+            fs::create_dir_all(&existing).unwrap();
+            for path in &[missing, existing.as_str()] {
+                let format = ImageFormat::from_path(path).unwrap();
+                match write_image(&framebuffer, format, &tone_mapping, Precision::Half, path) {
+                    Err(ImageError::Io(file, _)) => assert_eq!(file, *path),
+                    result => panic!("Expected an I/O error for {}, got {:?}", path, result),
+                }
+            }
+            fs::remove_dir(&existing).unwrap();
+        }
+    }
+
+    // Accepts a limited number of bytes and fails on everything after them
+    struct LimitedWriter {
+        written: usize,
+        limit: usize,
+    }
+
+    impl Write for LimitedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.written + buf.len() > self.limit {
+                return Err(io::Error::other("no space left"));
+            }
+            self.written += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_late_png_write_errors_are_returned() {
+        let image = Framebuffer::new(4, 4).to_rgba_bytes();
+        // The whole image fits into the buffer, so the device only fails when it is flushed
+        let full = BufWriter::new(LimitedWriter {
+            written: 0,
+            limit: 40,
+        });
+        match encode_png(full, &image, 4, 4, "full.png") {
+            Err(ImageError::Io(file, _)) => assert_eq!(file, "full.png"),
+            result => panic!("Expected an I/O error, got {:?}", result),
+        }
+
+        let roomy = LimitedWriter {
+            written: 0,
+            limit: 1 << 20,
+        };
+        assert!(encode_png(roomy, &image, 4, 4, "roomy.png").is_ok());
+    }
 
-    writer.write_image_data(&rgba_sequence).unwrap(); // Save
-}
\ No newline at end of file
+    #[test]
+    fn test_error_messages_name_the_file() {
+        let error = pfm::parse(b"P6 1 1 255\n", "input.pfm").unwrap_err();
+        assert_eq!(error.to_string(), "input.pfm: not a PFM file");
+        let error = ImageFormat::from_path("out.gif").unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("out.gif: unsupported image format"));
+        let error = exr::read_file("/rusttracer-does-not-exist.exr").unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("/rusttracer-does-not-exist.exr: "));
+    }
+}
@@ -0,0 +1,297 @@
+use std::error::Error;
+use std::fmt;
+
+// A small JSON parser that keeps track of the line every value starts on, so that errors
+// in files built on top of JSON can point to the offending line.
+// See https://www.json.org
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    // Keys are kept in the order they appear in the file
+    Object(Vec<(String, Json)>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Json {
+    pub value: JsonValue,
+    pub line: usize,
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self.value {
+            JsonValue::Object(ref members) => members
+                .iter()
+                .find(|member| member.0 == key)
+                .map(|member| &member.1),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self.value {
+            JsonValue::Null => "null",
+            JsonValue::Bool(_) => "a boolean",
+            JsonValue::Number(_) => "a number",
+            JsonValue::String(_) => "a string",
+            JsonValue::Array(_) => "an array",
+            JsonValue::Object(_) => "an object",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct JsonError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for JsonError {}
+
+pub fn parse(source: &str) -> Result<Json, JsonError> {
+    let mut parser = Parser {
+        chars: source.chars().collect(),
+        position: 0,
+        line: 1,
+        column: 1,
+    };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.peek().is_some() {
+        return Err(parser.error("unexpected characters after the end of the document"));
+    }
+    Ok(value)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> JsonError {
+        JsonError {
+            line: self.line,
+            column: self.column,
+            message: message.to_string(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), JsonError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.next();
+                Ok(())
+            }
+            Some(c) => Err(self.error(&format!("expected '{}', found '{}'", expected, c))),
+            None => Err(self.error(&format!("expected '{}', found end of file", expected))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.next();
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Json, JsonError> {
+        self.skip_whitespace();
+        let line = self.line;
+        let value = match self.peek() {
+            Some('{') => self.parse_object()?,
+            Some('[') => self.parse_array()?,
+            Some('"') => JsonValue::String(self.parse_string()?),
+            Some('t') => self.parse_literal("true", JsonValue::Bool(true))?,
+            Some('f') => self.parse_literal("false", JsonValue::Bool(false))?,
+            Some('n') => self.parse_literal("null", JsonValue::Null)?,
+            Some(c) if c == '-' || c.is_ascii_digit() => self.parse_number()?,
+            Some(c) => return Err(self.error(&format!("unexpected character '{}'", c))),
+            None => return Err(self.error("unexpected end of file")),
+        };
+        Ok(Json { value, line })
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: JsonValue) -> Result<JsonValue, JsonError> {
+        for expected in literal.chars() {
+            if self.peek() != Some(expected) {
+                return Err(self.error(&format!("invalid literal, expected '{}'", literal)));
+            }
+            self.next();
+        }
+        Ok(value)
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, JsonError> {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E' {
+                self.next();
+            } else {
+                break;
+            }
+        }
+        let text: String = self.chars[start..self.position].iter().collect();
+        text.parse()
+            .map(JsonValue::Number)
+            .map_err(|_| self.error(&format!("invalid number '{}'", text)))
+    }
+
+    fn parse_string(&mut self) -> Result<String, JsonError> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => {
+                    let escaped = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.parse_unicode_escape()?,
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    string.push(escaped);
+                }
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    fn parse_unicode_escape(&mut self) -> Result<char, JsonError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error("invalid unicode escape"))?;
+            code = code * 16 + digit;
+        }
+        // Surrogate pairs are not supported and replaced
+        Ok(::std::char::from_u32(code).unwrap_or('\u{fffd}'))
+    }
+
+    fn parse_array(&mut self) -> Result<JsonValue, JsonError> {
+        self.expect('[')?;
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.next();
+            return Ok(JsonValue::Array(elements));
+        }
+        loop {
+            elements.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(JsonValue::Array(elements)),
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<JsonValue, JsonError> {
+        self.expect('{')?;
+        let mut members: Vec<(String, Json)> = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.next();
+            return Ok(JsonValue::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            if members.iter().any(|member| member.0 == key) {
+                return Err(self.error(&format!("duplicate key '{}'", key)));
+            }
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            members.push((key, value));
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(JsonValue::Object(members)),
+                _ => return Err(self.error("expected ',' or '}' in object")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nested_document() {
+        let json =
+            parse("{\n  \"a\": [1, -2.5e1, true],\n  \"b\": {\"c\": \"d\\n\"},\n  \"e\": null\n}")
+                .unwrap();
+        let a = json.get("a").unwrap();
+        assert_eq!(a.line, 2);
+        match a.value {
+            JsonValue::Array(ref elements) => {
+                assert_eq!(elements[0].value, JsonValue::Number(1.0));
+                assert_eq!(elements[1].value, JsonValue::Number(-25.0));
+                assert_eq!(elements[2].value, JsonValue::Bool(true));
+            }
+            _ => panic!("expected an array"),
+        }
+        let c = json.get("b").and_then(|b| b.get("c")).unwrap();
+        assert_eq!(c.value, JsonValue::String("d\n".to_string()));
+        assert_eq!(c.line, 3);
+        assert_eq!(json.get("e").unwrap().value, JsonValue::Null);
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        let err = parse("{\n  \"a\": 1,\n  \"b\" 2\n}").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert_eq!(err.column, 7);
+    }
+
+    #[test]
+    fn test_parse_rejects_trailing_content() {
+        assert!(parse("[1, 2] 3").is_err());
+        assert!(parse("[1, 2,]").is_err());
+        assert!(parse("{\"a\": 1, \"a\": 2}").is_err());
+    }
+}
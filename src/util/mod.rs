@@ -1,2 +1,11 @@
+pub mod exr;
+pub mod framebuffer;
+pub mod hdr;
+pub mod image_error;
 pub mod image_output;
-pub mod ppm;
\ No newline at end of file
+pub mod json;
+pub mod obj;
+pub mod pfm;
+pub mod ppm;
+pub mod scene_file;
+pub mod tonemap;
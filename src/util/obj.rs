@@ -0,0 +1,563 @@
+use math::Vector3;
+use raytracing::Material;
+use shapes::TriangleMesh;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Loader for Wavefront OBJ files and their MTL material libraries. The file name of a diffuse
+// texture map is kept with the material, but textures are not rendered, so textured meshes are
+// shaded in their diffuse color.
+// See http://paulbourke.net/dataformats/obj/ and http://paulbourke.net/dataformats/mtl/
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(String, io::Error),
+    Parse {
+        file: String,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io(file, err) => write!(f, "{}: {}", file, err),
+            ObjError::Parse {
+                file,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", file, line, message),
+        }
+    }
+}
+
+impl Error for ObjError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjMaterial {
+    pub name: String,
+    // Colors are given in [0, 1] as in the MTL file
+    pub diffuse: Vector3,
+    pub specular: Vector3,
+    pub shininess: f64,
+    // The opacity, where 1.0 is fully opaque
+    pub dissolve: f64,
+    pub index_of_refraction: f64,
+    pub emission: Vector3,
+    // Not used by the tracer
+    pub diffuse_map: Option<String>,
+}
+
+impl ObjMaterial {
+    // Maps the MTL parameters onto the tracer's material model
+    pub fn to_material(&self) -> Material {
+        let specular_strength = self.specular.x.max(self.specular.y).max(self.specular.z);
+        Material {
+            color: &self.diffuse * 255.0,
+            specular_color: if specular_strength > 0.0 {
+                &self.specular * (255.0 / specular_strength)
+            } else {
+                Vector3::zero()
+            },
+            specular: specular_strength,
+            // MTL exponents range from 0 to 1000, phong shading needs at least 1
+            shininess: self.shininess.round().max(1.0) as i32,
+            transparency: 1.0 - self.dissolve,
+            index_of_refraction: self.index_of_refraction,
+            emission: &self.emission * 255.0,
+            ..Material::default()
+        }
+    }
+
+    pub fn new(name: &str) -> ObjMaterial {
+        ObjMaterial {
+            name: name.to_string(),
+            diffuse: Vector3::new(0.8, 0.8, 0.8),
+            specular: Vector3::zero(),
+            shininess: 0.0,
+            dissolve: 1.0,
+            index_of_refraction: 1.0,
+            emission: Vector3::zero(),
+            diffuse_map: None,
+        }
+    }
+}
+
+// A run of faces sharing the same group and material
+#[derive(Debug)]
+pub struct ObjObject {
+    pub name: String,
+    pub material: Option<String>,
+    pub mesh: TriangleMesh,
+}
+
+#[derive(Debug)]
+pub struct ObjModel {
+    pub objects: Vec<ObjObject>,
+    pub material_libraries: Vec<String>,
+    pub materials: HashMap<String, ObjMaterial>,
+}
+
+impl ObjModel {
+    // Maps the materials referenced by usemtl onto the meshes
+    pub fn apply_materials(&mut self) {
+        let materials = &self.materials;
+        for object in &mut self.objects {
+            let material = object
+                .material
+                .as_ref()
+                .and_then(|name| materials.get(name));
+            if let Some(material) = material {
+                object.mesh.material = material.to_material();
+            }
+        }
+    }
+
+    pub fn into_meshes(self) -> Vec<TriangleMesh> {
+        self.objects.into_iter().map(|object| object.mesh).collect()
+    }
+}
+
+pub fn load_obj(path: &Path) -> Result<ObjModel, ObjError> {
+    let file_name = path.display().to_string();
+    let source = fs::read_to_string(path).map_err(|err| ObjError::Io(file_name.clone(), err))?;
+    let mut model = parse_obj(&source, &file_name)?;
+
+    // Material libraries are referenced relative to the OBJ file
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    for library in &model.material_libraries {
+        let library_path = directory.join(library);
+        let library_name = library_path.display().to_string();
+        let source = fs::read_to_string(&library_path)
+            .map_err(|err| ObjError::Io(library_name.clone(), err))?;
+        model.materials.extend(parse_mtl(&source, &library_name)?);
+    }
+
+    model.apply_materials();
+    Ok(model)
+}
+
+struct Parser<'a> {
+    file: &'a str,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: String) -> ObjError {
+        ObjError::Parse {
+            file: self.file.to_string(),
+            line: self.line,
+            message,
+        }
+    }
+
+    fn parse_f64(&self, token: Option<&str>, what: &str) -> Result<f64, ObjError> {
+        match token {
+            Some(token) => token
+                .parse()
+                .map_err(|_| self.error(format!("invalid {} '{}'", what, token))),
+            None => Err(self.error(format!("missing {}", what))),
+        }
+    }
+
+    fn parse_fraction(&self, token: Option<&str>, what: &str) -> Result<f64, ObjError> {
+        let value = self.parse_f64(token, what)?;
+        if (0.0..=1.0).contains(&value) {
+            Ok(value)
+        } else {
+            Err(self.error(format!("{} {} is not between 0 and 1", what, value)))
+        }
+    }
+
+    fn parse_vector<'b, I: Iterator<Item = &'b str>>(
+        &self,
+        tokens: &mut I,
+        what: &str,
+    ) -> Result<Vector3, ObjError> {
+        let x = self.parse_f64(tokens.next(), what)?;
+        let y = self.parse_f64(tokens.next(), what)?;
+        let z = self.parse_f64(tokens.next(), what)?;
+        Ok(Vector3::new(x, y, z))
+    }
+
+    fn parse_index(&self, token: &str, count: usize, what: &str) -> Result<usize, ObjError> {
+        // Indices are 1-based, negative indices count backwards from the last element
+        let index: i64 = token
+            .parse()
+            .map_err(|_| self.error(format!("invalid {} index '{}'", what, token)))?;
+        let resolved = if index < 0 {
+            count as i64 + index
+        } else {
+            index - 1
+        };
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(self.error(format!(
+                "{} index {} out of range, {} defined so far",
+                what, index, count
+            )));
+        }
+        Ok(resolved as usize)
+    }
+}
+
+// A vertex of a face as indices into the global position, uv and normal lists
+type FaceVertex = (usize, Option<usize>, Option<usize>);
+
+struct ObjectBuilder {
+    name: String,
+    material: Option<String>,
+    smooth: bool,
+    vertex_lookup: HashMap<FaceVertex, usize>,
+    vertices: Vec<FaceVertex>,
+    indices: Vec<[usize; 3]>,
+}
+
+impl ObjectBuilder {
+    fn new(name: String, material: Option<String>, smooth: bool) -> ObjectBuilder {
+        ObjectBuilder {
+            name,
+            material,
+            smooth,
+            vertex_lookup: HashMap::new(),
+            vertices: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    fn add_vertex(&mut self, vertex: FaceVertex) -> usize {
+        let vertices = &mut self.vertices;
+        *self.vertex_lookup.entry(vertex).or_insert_with(|| {
+            vertices.push(vertex);
+            vertices.len() - 1
+        })
+    }
+
+    fn build(self, positions: &[Vector3], uvs: &[(f64, f64)], normals: &[Vector3]) -> ObjObject {
+        let mut mesh = TriangleMesh::new(
+            self.vertices
+                .iter()
+                .map(|v| positions[v.0].clone())
+                .collect(),
+            self.indices,
+            Material::default(),
+        );
+
+        if self.vertices.iter().any(|v| v.1.is_some()) {
+            mesh.uvs = self
+                .vertices
+                .iter()
+                .map(|v| v.1.map_or((0.0, 0.0), |i| uvs[i]))
+                .collect();
+        }
+
+        // Normals from the file are only used if every vertex has one, otherwise they
+        // are computed from the faces when smooth shading is requested
+        if !self.vertices.is_empty() && self.vertices.iter().all(|v| v.2.is_some()) {
+            mesh.normals = self
+                .vertices
+                .iter()
+                .map(|v| normals[v.2.unwrap()].normalize())
+                .collect();
+            mesh.smooth = true;
+        } else if self.smooth {
+            mesh.compute_vertex_normals();
+            mesh.smooth = true;
+        }
+
+        ObjObject {
+            name: self.name,
+            material: self.material,
+            mesh,
+        }
+    }
+}
+
+pub fn parse_obj(source: &str, file: &str) -> Result<ObjModel, ObjError> {
+    let mut parser = Parser { file, line: 0 };
+    let mut positions: Vec<Vector3> = Vec::new();
+    let mut uvs: Vec<(f64, f64)> = Vec::new();
+    let mut normals: Vec<Vector3> = Vec::new();
+    let mut material_libraries = Vec::new();
+    let mut objects = Vec::new();
+    let mut current = ObjectBuilder::new("default".to_string(), None, false);
+
+    for (number, line) in source.lines().enumerate() {
+        parser.line = number + 1;
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        match keyword {
+            "v" => positions.push(parser.parse_vector(&mut tokens, "vertex coordinate")?),
+            "vn" => normals.push(parser.parse_vector(&mut tokens, "normal coordinate")?),
+            "vt" => {
+                let u = parser.parse_f64(tokens.next(), "texture coordinate")?;
+                let v = match tokens.next() {
+                    Some(token) => parser.parse_f64(Some(token), "texture coordinate")?,
+                    None => 0.0,
+                };
+                uvs.push((u, v));
+            }
+            "f" => {
+                let mut face = Vec::new();
+                for token in tokens {
+                    let mut parts = token.split('/');
+                    let position = parser.parse_index(
+                        parts.next().unwrap_or(""),
+                        positions.len(),
+                        "vertex",
+                    )?;
+                    let uv = match parts.next() {
+                        Some("") | None => None,
+                        Some(part) => Some(parser.parse_index(part, uvs.len(), "texture")?),
+                    };
+                    let normal = match parts.next() {
+                        Some("") | None => None,
+                        Some(part) => Some(parser.parse_index(part, normals.len(), "normal")?),
+                    };
+                    face.push(current.add_vertex((position, uv, normal)));
+                }
+                if face.len() < 3 {
+                    return Err(parser.error(format!(
+                        "face needs at least 3 vertices, got {}",
+                        face.len()
+                    )));
+                }
+                // Triangulate quads and ngons as a fan around the first vertex
+                for i in 1..face.len() - 1 {
+                    current.indices.push([face[0], face[i], face[i + 1]]);
+                }
+            }
+            "g" | "o" | "usemtl" => {
+                let argument = tokens.collect::<Vec<_>>().join(" ");
+                if keyword == "usemtl" && argument.is_empty() {
+                    return Err(parser.error("usemtl without material name".to_string()));
+                }
+                let (name, material) = if keyword == "usemtl" {
+                    (current.name.clone(), Some(argument))
+                } else {
+                    let name = if argument.is_empty() {
+                        "default".to_string()
+                    } else {
+                        argument
+                    };
+                    (name, current.material.clone())
+                };
+                let next = ObjectBuilder::new(name, material, current.smooth);
+                let finished = ::std::mem::replace(&mut current, next);
+                if !finished.indices.is_empty() {
+                    objects.push(finished.build(&positions, &uvs, &normals));
+                }
+            }
+            "s" => {
+                current.smooth = match tokens.next() {
+                    Some("off") | Some("0") | None => false,
+                    Some(_) => true,
+                }
+            }
+            "mtllib" => material_libraries.extend(tokens.map(|token| token.to_string())),
+            // Lines, points, curves and other statements are not supported and ignored
+            _ => (),
+        }
+    }
+
+    if !current.indices.is_empty() {
+        objects.push(current.build(&positions, &uvs, &normals));
+    }
+
+    Ok(ObjModel {
+        objects,
+        material_libraries,
+        materials: HashMap::new(),
+    })
+}
+
+pub fn parse_mtl(source: &str, file: &str) -> Result<HashMap<String, ObjMaterial>, ObjError> {
+    let mut parser = Parser { file, line: 0 };
+    let mut materials = HashMap::new();
+    let mut current: Option<ObjMaterial> = None;
+
+    for (number, line) in source.lines().enumerate() {
+        parser.line = number + 1;
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            let name = tokens.collect::<Vec<_>>().join(" ");
+            if name.is_empty() {
+                return Err(parser.error("newmtl without material name".to_string()));
+            }
+            if let Some(material) = current.take() {
+                materials.insert(material.name.clone(), material);
+            }
+            current = Some(ObjMaterial::new(&name));
+            continue;
+        }
+
+        let material = match current.as_mut() {
+            Some(material) => material,
+            None => return Err(parser.error(format!("'{}' before any newmtl statement", keyword))),
+        };
+        match keyword {
+            "Kd" => material.diffuse = parser.parse_vector(&mut tokens, "diffuse color")?,
+            "Ks" => material.specular = parser.parse_vector(&mut tokens, "specular color")?,
+            "Ke" => material.emission = parser.parse_vector(&mut tokens, "emissive color")?,
+            "Ns" => material.shininess = parser.parse_f64(tokens.next(), "specular exponent")?,
+            "d" => material.dissolve = parser.parse_fraction(tokens.next(), "dissolve")?,
+            "Tr" => {
+                material.dissolve = 1.0 - parser.parse_fraction(tokens.next(), "transparency")?
+            }
+            "Ni" => {
+                material.index_of_refraction =
+                    parser.parse_f64(tokens.next(), "index of refraction")?
+            }
+            "map_Kd" => {
+                // Options may precede the file name, which always comes last
+                match tokens.last() {
+                    Some(path) => material.diffuse_map = Some(path.to_string()),
+                    None => return Err(parser.error("map_Kd without file name".to_string())),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    if let Some(material) = current {
+        materials.insert(material.name.clone(), material);
+    }
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_parse_quad_is_triangulated() {
+        let source = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n";
+        let model = parse_obj(source, "quad.obj").unwrap();
+        assert_eq!(model.objects.len(), 1);
+        let mesh = &model.objects[0].mesh;
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn test_parse_vertex_attributes_and_negative_indices() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\n\
+                      vt 0 0\nvt 1 0\nvt 0 1\n\
+                      vn 0 0 2\n\
+                      f -3/1/1 -2/2/1 -1/3/1\n";
+        let model = parse_obj(source, "triangle.obj").unwrap();
+        let mesh = &model.objects[0].mesh;
+        assert_eq!(mesh.uvs, vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]);
+        assert_eq!(mesh.normals[2], Vector3::new(0.0, 0.0, 1.0));
+        assert!(mesh.smooth);
+    }
+
+    #[test]
+    fn test_parse_splits_groups_and_materials() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\n\
+                      g first\nusemtl red\nf 1 2 3\n\
+                      usemtl blue\nf 1 2 3\n\
+                      g second\nf 3 2 1\n";
+        let model = parse_obj(source, "groups.obj").unwrap();
+        let summary: Vec<(&str, Option<&str>)> = model
+            .objects
+            .iter()
+            .map(|o| (o.name.as_str(), o.material.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("first", Some("red")),
+                ("first", Some("blue")),
+                ("second", Some("blue")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_error_has_line_number() {
+        let source = "v 0 0 0\nv 1 0 0\n\nv 0 one 0\n";
+        match parse_obj(source, "broken.obj") {
+            Err(ObjError::Parse { line, .. }) => assert_eq!(line, 4),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_error_on_index_out_of_range() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n";
+        let err = parse_obj(source, "broken.obj").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "broken.obj:4: vertex index 4 out of range, 3 defined so far"
+        );
+    }
+
+    #[test]
+    fn test_parse_mtl_error_on_dissolve_out_of_range() {
+        let err = parse_mtl("newmtl glass\nd 1.5\n", "glass.mtl").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "glass.mtl:2: dissolve 1.5 is not between 0 and 1"
+        );
+        let err = parse_mtl("newmtl glass\n\nTr -0.5\n", "glass.mtl").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "glass.mtl:3: transparency -0.5 is not between 0 and 1"
+        );
+    }
+
+    #[test]
+    fn test_parse_mtl() {
+        let source = "newmtl glass\nKd 0.1 0.2 0.3\nKs 1 1 1\nNs 96\nd 0.25\nNi 1.5\n\
+                      Ke 0 0.5 1\nmap_Kd -s 2 2 1 textures/glass.png\n";
+        let materials = parse_mtl(source, "glass.mtl").unwrap();
+        let glass = &materials["glass"];
+        assert_eq!(glass.diffuse, Vector3::new(0.1, 0.2, 0.3));
+        assert_eq!(glass.specular, Vector3::new(1.0, 1.0, 1.0));
+        assert_eq!(glass.shininess, 96.0);
+        assert_eq!(glass.dissolve, 0.25);
+        assert_eq!(glass.index_of_refraction, 1.5);
+        assert_eq!(glass.diffuse_map, Some("textures/glass.png".to_string()));
+
+        let material = glass.to_material();
+        assert_eq!(material.color, &Vector3::new(0.1, 0.2, 0.3) * 255.0);
+        assert_eq!(material.specular_color, Vector3::new(255.0, 255.0, 255.0));
+        assert_eq!(material.shininess, 96);
+        assert_eq!(material.transparency, 0.75);
+        assert_eq!(material.index_of_refraction, 1.5);
+        assert_eq!(material.emission, Vector3::new(0.0, 127.5, 255.0));
+    }
+
+    #[test]
+    fn test_load_obj_with_material_library() {
+        let directory = env::temp_dir().join("rusttracer_obj_test");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("red.mtl"), "newmtl red\nKd 1 0 0\n").unwrap();
+        fs::write(
+            directory.join("red.obj"),
+            "mtllib red.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl red\nf 1 2 3\n",
+        )
+        .unwrap();
+
+        let model = load_obj(&directory.join("red.obj")).unwrap();
+        assert_eq!(model.objects[0].mesh.material.color, Vector3::red());
+        assert_eq!(model.into_meshes().len(), 1);
+    }
+}
@@ -0,0 +1,182 @@
+use std::fs;
+use std::path::Path;
+use util::framebuffer::{Framebuffer, LinearRGB};
+use util::image_error::ImageError;
+
+// Reads and writes Portable Float Maps, the floating point sibling of PPM. The header is
+// followed by raw 32 bit floats with rows ordered from the bottom to the top of the image.
+// The sign of the scale in the header gives the byte order, negative means little endian.
+// See http://www.pauldebevec.com/Research/HDR/PFM/
+
+pub fn get_bytes(framebuffer: &Framebuffer) -> Vec<u8> {
+    let mut bytes = format!(
+        "PF\n{} {}\n-1.0\n",
+        framebuffer.width(),
+        framebuffer.height()
+    )
+    .into_bytes();
+    let width = framebuffer.width() as usize;
+    if width == 0 {
+        return bytes;
+    }
+    for row in framebuffer.pixels().chunks(width).rev() {
+        for pixel in row {
+            for value in &[pixel.r, pixel.g, pixel.b] {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+    bytes
+}
+
+pub fn write_file(framebuffer: &Framebuffer, filename: &str) -> Result<(), ImageError> {
+    fs::write(Path::new(filename), get_bytes(framebuffer))
+        .map_err(|err| ImageError::Io(filename.to_string(), err))
+}
+
+pub fn parse(bytes: &[u8], file: &str) -> Result<Framebuffer, ImageError> {
+    decode(bytes).map_err(|message| ImageError::Decoding(file.to_string(), message))
+}
+
+fn decode(bytes: &[u8]) -> Result<Framebuffer, String> {
+    // The header consists of three whitespace separated tokens, followed by a single
+    // whitespace character before the data starts
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    while tokens.len() < 4 {
+        while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        let start = position;
+        while position < bytes.len() && !bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if start == position {
+            return Err(invalid("unexpected end of the header"));
+        }
+        tokens.push(String::from_utf8_lossy(&bytes[start..position]).into_owned());
+    }
+    let data = &bytes[(position + 1).min(bytes.len())..];
+
+    let channels = match tokens[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid("not a PFM file")),
+    };
+    let (width, height, scale): (u32, u32, f32) =
+        match (tokens[1].parse(), tokens[2].parse(), tokens[3].parse()) {
+            (Ok(width), Ok(height), Ok(scale)) => (width, height, scale),
+            _ => return Err(invalid("invalid header")),
+        };
+    if scale == 0.0 || !scale.is_finite() {
+        return Err(invalid("invalid scale"));
+    }
+    let little_endian = scale < 0.0;
+
+    let size = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(channels * 4))
+        .ok_or_else(|| invalid("the image is too large"))?;
+    if data.len() < size {
+        return Err(invalid("unexpected end of the pixel data"));
+    }
+    let floats: Vec<f32> = data[..size]
+        .chunks(4)
+        .map(|chunk| {
+            let bytes = [chunk[0], chunk[1], chunk[2], chunk[3]];
+            if little_endian {
+                f32::from_le_bytes(bytes)
+            } else {
+                f32::from_be_bytes(bytes)
+            }
+        })
+        .collect();
+
+    let mut framebuffer = Framebuffer::new(width, height);
+    if width > 0 {
+        for (row, y) in floats
+            .chunks(width as usize * channels)
+            .zip((0..height).rev())
+        {
+            for (x, pixel) in row.chunks(channels).enumerate() {
+                let color = if channels == 3 {
+                    LinearRGB::new(pixel[0], pixel[1], pixel[2])
+                } else {
+                    LinearRGB::new(pixel[0], pixel[0], pixel[0])
+                };
+                framebuffer.set_pixel(x as u32, y, color);
+            }
+        }
+    }
+    Ok(framebuffer)
+}
+
+pub fn read_file(filename: &str) -> Result<Framebuffer, ImageError> {
+    let bytes =
+        fs::read(Path::new(filename)).map_err(|err| ImageError::Io(filename.to_string(), err))?;
+    parse(&bytes, filename)
+}
+
+fn invalid(message: &str) -> String {
+    message.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn test_image() -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.set_pixel(0, 0, LinearRGB::new(1.0, 2.0, 3.0));
+        framebuffer.set_pixel(2, 1, LinearRGB::new(-0.5, 1e-7, 12345.678));
+        framebuffer
+    }
+
+    #[test]
+    fn test_bottom_row_comes_first() {
+        let bytes = get_bytes(&test_image());
+        let header = b"PF\n3 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(bytes.len(), header.len() + 3 * 2 * 3 * 4);
+        // The top left pixel is the first one of the second row in the file
+        let offset = header.len() + 3 * 3 * 4;
+        assert_eq!(&bytes[offset..offset + 4], &1.0f32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let image = test_image();
+        assert_eq!(parse(&get_bytes(&image), "test.pfm").unwrap(), image);
+    }
+
+    #[test]
+    fn test_big_endian_grayscale() {
+        let mut bytes = b"Pf\n2 1\n1.0\n".to_vec();
+        bytes.extend_from_slice(&0.5f32.to_be_bytes());
+        bytes.extend_from_slice(&4.0f32.to_be_bytes());
+        let image = parse(&bytes, "test.pfm").unwrap();
+        assert_eq!(image.get_pixel(0, 0), Some(LinearRGB::new(0.5, 0.5, 0.5)));
+        assert_eq!(image.get_pixel(1, 0), Some(LinearRGB::new(4.0, 4.0, 4.0)));
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let path = env::temp_dir().join(format!("rusttracer-{}.pfm", std::process::id()));
+        let path = path.to_str().unwrap();
+        let image = test_image();
+        write_file(&image, path).unwrap();
+        let result = read_file(path);
+        fs::remove_file(path).unwrap();
+        assert_eq!(result.unwrap(), image);
+    }
+
+    #[test]
+    fn test_truncated_file() {
+        let bytes = get_bytes(&test_image());
+        assert!(parse(&bytes[..bytes.len() - 1], "test.pfm").is_err());
+        assert!(parse(b"PF\n3", "test.pfm").is_err());
+        // Sizes whose number of bytes doesn't fit in memory
+        assert!(parse(b"PF\n4000000000 4000000000\n-1.0\n\0\0\0\0", "test.pfm").is_err());
+    }
+}
@@ -1,113 +1,406 @@
-use std::fs::File;
-use std::io;
-use std::io::Write;
+use std::fs;
 use std::path::Path;
+use util::framebuffer::{Framebuffer, LinearRGB};
+use util::image_error::ImageError;
+
+// Reads and writes the netpbm formats: bitmaps (P1, P4), graymaps (P2, P5), pixmaps (P3, P6),
+// each in a plain text and a binary flavour, and PAM (P7). Samples are scaled by the maximum
+// value of the file into [0, 1], no transfer function is applied in either direction.
+// See http://netpbm.sourceforge.net/doc/pnm.html and http://netpbm.sourceforge.net/doc/pam.html
 
 // FROM https://rosettacode.org/wiki/Bitmap/Write_a_PPM_file#Rust
 
-pub struct RGB {
-    r: u8,
-    g: u8,
-    b: u8,
+// Returns the entire binary P6 file as bytes, so we can also write it to stdout
+pub fn get_bytes(framebuffer: &Framebuffer) -> Vec<u8> {
+    let mut ppm_bytes =
+        format!("P6 {} {} 255\n", framebuffer.width(), framebuffer.height()).into_bytes();
+    ppm_bytes.append(&mut framebuffer.to_rgb_bytes());
+    ppm_bytes
 }
 
-impl RGB {
-    pub fn new(r: u8, g: u8, b: u8) -> RGB {
-        RGB { r, g, b}
+// Returns a plain text P3 file with one pixel per line, which makes renders easy to diff
+pub fn get_ascii_bytes(framebuffer: &Framebuffer) -> Vec<u8> {
+    let mut ppm = format!(
+        "P3\n{} {}\n255\n",
+        framebuffer.width(),
+        framebuffer.height()
+    );
+    for pixel in framebuffer.to_rgb_bytes().chunks(3) {
+        ppm.push_str(&format!("{} {} {}\n", pixel[0], pixel[1], pixel[2]));
     }
+    ppm.into_bytes()
 }
 
-pub struct PPM {
-    height: u32,
-    width: u32,
-    data: Vec<u8>,
+pub fn write_file(framebuffer: &Framebuffer, filename: &str) -> Result<(), ImageError> {
+    fs::write(Path::new(filename), get_bytes(framebuffer))
+        .map_err(|err| ImageError::Io(filename.to_string(), err))
 }
 
-impl PPM {
-    pub fn new(height: u32, width: u32) -> PPM {
-        let size = 3 * height * width;
-        let buffer = vec![0; size as usize];
-        PPM {
-            height: height,
-            width: width,
-            data: buffer,
-        }
+pub fn write_ascii_file(framebuffer: &Framebuffer, filename: &str) -> Result<(), ImageError> {
+    fs::write(Path::new(filename), get_ascii_bytes(framebuffer))
+        .map_err(|err| ImageError::Io(filename.to_string(), err))
+}
+
+pub fn parse(bytes: &[u8], file: &str) -> Result<Framebuffer, ImageError> {
+    decode(bytes).map_err(|message| ImageError::Decoding(file.to_string(), message))
+}
+
+fn decode(bytes: &[u8]) -> Result<Framebuffer, String> {
+    let mut reader = Reader { bytes, position: 2 };
+    let kind = match bytes {
+        [b'P', kind @ b'1'..=b'7', ..] => *kind,
+        _ => return Err(invalid("not a PNM or PAM file")),
+    };
+    if kind == b'7' {
+        return decode_pam(&mut reader);
     }
 
-    fn buffer_size(&self) -> u32 {
-        3 * self.height * self.width
+    let width = reader.number()?;
+    let height = reader.number()?;
+    let maxval = match kind {
+        b'1' | b'4' => 1,
+        _ => reader.number()?,
+    };
+    if maxval == 0 || maxval > 65535 {
+        return Err(invalid("the maximum value must be between 1 and 65535"));
     }
+    let depth = match kind {
+        b'3' | b'6' => 3,
+        _ => 1,
+    };
+    let count = sample_count(width, height, depth)?;
 
-    fn get_offset(&self, x: u32, y: u32) -> Option<usize> {
-        let offset = (y * self.width * 3) + (x * 3);
-        if offset < self.buffer_size() {
-            Some(offset as usize)
-        } else {
-            None
+    let samples = match kind {
+        b'1' => {
+            // The digits of plain bitmaps do not need to be separated by whitespace
+            let mut samples = Vec::new();
+            for _ in 0..count {
+                samples.push(reader.bit()?);
+            }
+            samples
+        }
+        b'2' | b'3' => {
+            let mut samples = Vec::new();
+            for _ in 0..count {
+                samples.push(reader.number()?);
+            }
+            samples
+        }
+        b'4' => {
+            // Every row is padded to a whole number of bytes, the first pixel is the high bit
+            reader.raster_start()?;
+            let row_bytes = (width as usize).div_ceil(8);
+            let data = reader.take(row_bytes * height as usize)?;
+            let mut samples = Vec::with_capacity(count);
+            if row_bytes > 0 {
+                for row in data.chunks(row_bytes) {
+                    for x in 0..width as usize {
+                        samples.push(u32::from(row[x / 8] >> (7 - x % 8) & 1));
+                    }
+                }
+            }
+            samples
+        }
+        _ => {
+            reader.raster_start()?;
+            reader.binary_samples(count, maxval)?
         }
+    };
+    // In bitmaps a set bit is black
+    let samples = if kind == b'1' || kind == b'4' {
+        samples.into_iter().map(|bit| 1 - bit).collect()
+    } else {
+        samples
+    };
+    to_framebuffer(width, height, depth, maxval, &samples)
+}
+
+fn decode_pam(reader: &mut Reader) -> Result<Framebuffer, String> {
+    // The header is a list of lines with a keyword and a value, closed by ENDHDR
+    let (mut width, mut height, mut depth, mut maxval) = (None, None, None, None);
+    loop {
+        let line = reader.line()?;
+        let mut fields = line.split_whitespace();
+        let keyword = match fields.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+        let value = fields.next().map(str::parse::<u32>);
+        match (keyword, value) {
+            ("ENDHDR", _) => break,
+            ("WIDTH", Some(Ok(value))) => width = Some(value),
+            ("HEIGHT", Some(Ok(value))) => height = Some(value),
+            ("DEPTH", Some(Ok(value))) => depth = Some(value),
+            ("MAXVAL", Some(Ok(value))) => maxval = Some(value),
+            // The channels are identified by the depth alone
+            ("TUPLTYPE", _) => {}
+            _ => return Err(invalid(&format!("invalid header line {}", line))),
+        }
+    }
+
+    let (width, height, depth, maxval) = match (width, height, depth, maxval) {
+        (Some(width), Some(height), Some(depth), Some(maxval)) => (width, height, depth, maxval),
+        _ => return Err(invalid("WIDTH, HEIGHT, DEPTH and MAXVAL are required")),
+    };
+    if maxval == 0 || maxval > 65535 {
+        return Err(invalid("the maximum value must be between 1 and 65535"));
+    }
+    if depth == 0 || depth > 4 {
+        return Err(invalid(&format!(
+            "unsupported depth {}, only grayscale and RGB with optional alpha are supported",
+            depth
+        )));
     }
+    let count = sample_count(width, height, depth as usize)?;
+    let samples = reader.binary_samples(count, maxval)?;
+    to_framebuffer(width, height, depth as usize, maxval, &samples)
+}
+
+fn sample_count(width: u32, height: u32, depth: usize) -> Result<usize, String> {
+    (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(depth))
+        .ok_or_else(|| invalid("the image is too large"))
+}
 
-    pub fn get_pixel(&self, x: u32, y: u32) -> Option<RGB> {
-        match self.get_offset(x, y) {
-            Some(offset) => {
-                let r = self.data[offset];
-                let g = self.data[offset + 1];
-                let b = self.data[offset + 2];
-                Some(RGB {
-                    r: r,
-                    g: g,
-                    b: b,
-                })
+fn to_framebuffer(
+    width: u32,
+    height: u32,
+    depth: usize,
+    maxval: u32,
+    samples: &[u32],
+) -> Result<Framebuffer, String> {
+    if samples.iter().any(|&sample| sample > maxval) {
+        return Err(invalid("sample exceeds the maximum value"));
+    }
+    let scale = |sample: u32| sample as f32 / maxval as f32;
+    // A fourth channel or a second one of grayscale images is alpha, which we drop
+    let pixels = samples
+        .chunks(depth)
+        .map(|tuple| {
+            if depth >= 3 {
+                LinearRGB::new(scale(tuple[0]), scale(tuple[1]), scale(tuple[2]))
+            } else {
+                let gray = scale(tuple[0]);
+                LinearRGB::new(gray, gray, gray)
             }
-            None => None,
+        })
+        .collect();
+    Ok(Framebuffer::from_pixels(width, height, pixels).expect("Size matches the pixels"))
+}
+
+pub fn read_file(filename: &str) -> Result<Framebuffer, ImageError> {
+    let bytes =
+        fs::read(Path::new(filename)).map_err(|err| ImageError::Io(filename.to_string(), err))?;
+    parse(&bytes, filename)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.position + count > self.bytes.len() {
+            return Err(invalid("unexpected end of the file"));
         }
+        self.position += count;
+        Ok(&self.bytes[self.position - count..self.position])
     }
 
-    pub fn set_pixel(&mut self, x: u32, y: u32, color: RGB) -> bool {
-        match self.get_offset(x, y) {
-            Some(offset) => {
-                self.data[offset] = color.r;
-                self.data[offset + 1] = color.g;
-                self.data[offset + 2] = color.b;
-                true
+    fn skip_whitespace(&mut self) {
+        // Comments start with # and run until the end of the line
+        while self.position < self.bytes.len() {
+            match self.bytes[self.position] {
+                b'#' => {
+                    while self.position < self.bytes.len() && self.bytes[self.position] != b'\n' {
+                        self.position += 1;
+                    }
+                }
+                byte if byte.is_ascii_whitespace() => self.position += 1,
+                _ => break,
             }
-            None => false,
         }
     }
 
-    pub fn write_file(&self, filename: &str) -> io::Result<()> {
-        let path = Path::new(filename);
-        let mut file = try!(File::create(&path));
-        let header = format!("P6 {} {} 255\n", self.width, self.height);
-        try!(file.write(header.as_bytes()));
-        try!(file.write(&self.data));
-        Ok(())
+    fn number(&mut self) -> Result<u32, String> {
+        self.skip_whitespace();
+        let start = self.position;
+        while self.position < self.bytes.len() && self.bytes[self.position].is_ascii_digit() {
+            self.position += 1;
+        }
+        if start == self.position {
+            return Err(invalid(if self.position == self.bytes.len() {
+                "unexpected end of the file"
+            } else {
+                "expected a number"
+            }));
+        }
+        String::from_utf8_lossy(&self.bytes[start..self.position])
+            .parse()
+            .map_err(|_| invalid("number out of range"))
     }
 
-    // Added a method to get the entire file as bytes so we can write it to stdout
-    pub fn get_bytes(&self) -> Vec<u8> {
-        let mut ppm_bytes = format!("P6 {} {} 255\n", self.width, self.height).into_bytes();
-        ppm_bytes.append(&mut self.data.clone());
-        ppm_bytes
+    fn bit(&mut self) -> Result<u32, String> {
+        self.skip_whitespace();
+        match self.take(1)?[0] {
+            b'0' => Ok(0),
+            b'1' => Ok(1),
+            _ => Err(invalid("expected 0 or 1")),
+        }
     }
 
-    pub fn get_raw_bytes(&self) -> Vec<u8> {
-        let num_rgb_values = self.data.len() / 3;
-        let mut rgba_bytes: Vec<u8> = Vec::with_capacity((num_rgb_values * 4) as usize);
-        for i in 0..num_rgb_values {
-            rgba_bytes.push(self.data[i * 3 + 0]);
-            rgba_bytes.push(self.data[i * 3 + 1]);
-            rgba_bytes.push(self.data[i * 3 + 2]);
-            rgba_bytes.push(255);
+    fn line(&mut self) -> Result<String, String> {
+        let start = self.position;
+        while self.take(1)?[0] != b'\n' {}
+        Ok(String::from_utf8_lossy(&self.bytes[start..self.position - 1]).into_owned())
+    }
+
+    fn raster_start(&mut self) -> Result<(), String> {
+        // A single whitespace character separates the header from binary data
+        if !self.take(1)?[0].is_ascii_whitespace() {
+            return Err(invalid("expected whitespace after the header"));
         }
-        rgba_bytes
+        Ok(())
+    }
+
+    fn binary_samples(&mut self, count: usize, maxval: u32) -> Result<Vec<u32>, String> {
+        // Samples take two big endian bytes if the maximum value does not fit in one
+        if maxval < 256 {
+            Ok(self
+                .take(count)?
+                .iter()
+                .map(|&byte| u32::from(byte))
+                .collect())
+        } else {
+            let data = self.take(
+                count
+                    .checked_mul(2)
+                    .ok_or_else(|| invalid("the image is too large"))?,
+            )?;
+            Ok(data
+                .chunks(2)
+                .map(|pair| u32::from(u16::from_be_bytes([pair[0], pair[1]])))
+                .collect())
+        }
+    }
+}
+
+fn invalid(message: &str) -> String {
+    message.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn test_image() -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.set_pixel(0, 0, LinearRGB::new(1.0, 0.2, 0.0));
+        framebuffer.set_pixel(2, 1, LinearRGB::new(0.4, 0.6, 0.8));
+        framebuffer
     }
 
-    pub fn get_width(&self) -> u32 {
-        self.width
+    #[test]
+    fn test_header_has_width_first() {
+        let mut framebuffer = Framebuffer::new(3, 1);
+        framebuffer.set_pixel(2, 0, LinearRGB::new(0.2, 0.6, 1.5));
+        let bytes = get_bytes(&framebuffer);
+        let header = b"P6 3 1 255\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(&bytes[header.len()..], &[0, 0, 0, 0, 0, 0, 51, 153, 255]);
     }
 
-    pub fn get_height(&self) -> u32 {
-        self.height
+    #[test]
+    fn test_ascii_output() {
+        let bytes = get_ascii_bytes(&test_image());
+        let expected = "P3\n3 2\n255\n255 51 0\n0 0 0\n0 0 0\n0 0 0\n0 0 0\n102 153 204\n";
+        assert_eq!(String::from_utf8(bytes).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let image = test_image();
+        assert_eq!(parse(&get_bytes(&image), "test.ppm").unwrap(), image);
+        assert_eq!(parse(&get_ascii_bytes(&image), "test.ppm").unwrap(), image);
+    }
+
+    #[test]
+    fn test_bitmaps() {
+        // Set bits are black, binary rows are padded to whole bytes
+        let plain = parse(b"P1\n# a comment\n3 2\n101\n0 1 0", "test.pbm").unwrap();
+        let binary = parse(b"P4 3 2\n\xa0\x40", "test.pbm").unwrap();
+        assert_eq!(plain, binary);
+        let white = LinearRGB::new(1.0, 1.0, 1.0);
+        assert_eq!(plain.get_pixel(0, 0), Some(LinearRGB::black()));
+        assert_eq!(plain.get_pixel(1, 0), Some(white));
+        assert_eq!(plain.get_pixel(1, 1), Some(LinearRGB::black()));
+        assert_eq!(plain.get_pixel(2, 1), Some(white));
+    }
+
+    #[test]
+    fn test_graymaps_and_sixteen_bit_samples() {
+        let plain = parse(b"P2 2 1 # comment\n 65535\n65535 13107", "test.pgm").unwrap();
+        let binary = parse(b"P5 2 1 65535\n\xff\xff\x33\x33", "test.pgm").unwrap();
+        assert_eq!(plain, binary);
+        assert_eq!(plain.get_pixel(1, 0), Some(LinearRGB::new(0.2, 0.2, 0.2)));
+
+        let pixmap = parse(b"P6 1 1 1023\n\x03\xff\x00\x00\x01\x00", "test.ppm").unwrap();
+        assert_eq!(
+            pixmap.get_pixel(0, 0),
+            Some(LinearRGB::new(1.0, 0.0, 256.0 / 1023.0))
+        );
+    }
+
+    #[test]
+    fn test_pam() {
+        let header = "P7\nWIDTH 2\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\n# alpha is dropped\n\
+                      TUPLTYPE RGB_ALPHA\nENDHDR\n";
+        let mut bytes = header.as_bytes().to_vec();
+        bytes.extend_from_slice(&[255, 0, 51, 128, 0, 102, 0, 255]);
+        let image = parse(&bytes, "test.pam").unwrap();
+        assert_eq!(image.get_pixel(0, 0), Some(LinearRGB::new(1.0, 0.0, 0.2)));
+        assert_eq!(image.get_pixel(1, 0), Some(LinearRGB::new(0.0, 0.4, 0.0)));
+
+        let gray = parse(
+            b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nMAXVAL 1\nTUPLTYPE BLACKANDWHITE\nENDHDR\n\x01",
+            "test.pam",
+        )
+        .unwrap();
+        assert_eq!(gray.get_pixel(0, 0), Some(LinearRGB::new(1.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let path = env::temp_dir().join(format!("rusttracer-{}.ppm", std::process::id()));
+        let path = path.to_str().unwrap();
+        let image = test_image();
+        write_ascii_file(&image, path).unwrap();
+        let result = read_file(path);
+        fs::remove_file(path).unwrap();
+        assert_eq!(result.unwrap(), image);
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        assert!(parse(b"#?RADIANCE\n", "test.ppm").is_err());
+        assert!(parse(b"P6 2 2 255\n\x00\x00", "test.ppm").is_err());
+        assert!(parse(b"P3 1 1 255\n0 0 256", "test.ppm").is_err());
+        assert!(parse(b"P5 1 1 70000\n\x00\x00", "test.pgm").is_err());
+        assert!(parse(b"P1 2 1\n0 2", "test.pbm").is_err());
+        assert!(parse(
+            b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 3\nENDHDR\n\x00\x00\x00",
+            "test.pam"
+        )
+        .is_err());
+        match parse(b"P3 1 1 255\n0 0", "test.ppm") {
+            Err(ImageError::Decoding(file, message)) => {
+                assert_eq!(file, "test.ppm");
+                assert_eq!(message, "unexpected end of the file");
+            }
+            result => panic!("Expected a decoding error, got {:?}", result),
+        }
     }
 }
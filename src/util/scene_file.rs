@@ -0,0 +1,1123 @@
+use math::Vector3;
+use raytracing::{
+    Background, Camera, DirectionalLight, DiskLight, EnvironmentMap, Falloff, FovAxis, Light,
+    LightUnit, Material, RectLight, Scene, SphereLight, SpotLight,
+};
+use shapes::{Disk, Plane, Sphere, Triangle};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use util::image_error::ImageError;
+use util::json::{self, Json, JsonError, JsonValue};
+use util::obj::{self, ObjError};
+use util::{hdr, pfm};
+
+// Loads scenes described in JSON files. A scene file looks like this, where every top level
+// key is optional and colors are given in [0, 255]:
+//
+// {
+//     "resolution": { "width": 512, "height": 512 },
+//     "camera": {
+//         "position": [0, 0, -5], "look_at": [0, 0, 5], "up": [0, 1, 0], "roll": 0,
+//         "fov": 75, "fov_axis": "horizontal"
+//     },
+//     "ambient_light": 0.1,
+//     "background": { "type": "gradient", "bottom": [255, 255, 255], "top": [120, 170, 255] },
+//     "lights": [
+//         { "position": [0, -5, 4], "intensity": 1.2 },
+//         {
+//             "position": [3, 4, 2], "intensity": 600, "unit": "W", "color": [255, 200, 150],
+//             "falloff": "inverse_square"
+//         },
+//         { "type": "directional", "direction": [1, -2, 1], "intensity": 0.8 },
+//         {
+//             "type": "spot", "position": [0, 5, 0], "direction": [0, -1, 0],
+//             "inner_angle": 20, "outer_angle": 30, "intensity": 2
+//         },
+//         {
+//             "type": "rect", "center": [0, 5, 3], "u": [2, 0, 0], "v": [0, 0, 1],
+//             "intensity": 1.5, "samples": 25
+//         },
+//         {
+//             "type": "disk", "center": [0, 5, 8], "normal": [0, -1, 0], "radius": 1,
+//             "intensity": 2
+//         },
+//         { "type": "sphere", "center": [-3, 2, 5], "radius": 0.5, "intensity": 1 }
+//     ],
+//     "materials": {
+//         "red": { "color": [255, 0, 0] },
+//         "chrome": {
+//             "color": [200, 200, 200], "diffuse": 0.5, "ambient": 1.0,
+//             "specular": 1.0, "specular_color": [255, 255, 255], "shininess": 60,
+//             "reflectivity": 0.8
+//         },
+//         "green_glass": {
+//             "color": [0, 0, 0], "transparency": 1.0, "index_of_refraction": 1.5,
+//             "absorption": [0.4, 0.05, 0.4]
+//         },
+//         "lamp": { "color": [0, 0, 0], "emission": [2550, 2300, 1800] }
+//     },
+//     "objects": [
+//         { "type": "sphere", "center": [0, 0, 5], "radius": 1.5, "material": "red" },
+//         { "type": "plane", "point": [0, -3, 0], "normal": [0, 1, 0], "color": [0, 255, 0] },
+//         { "type": "disk", "center": [0, 0, 9], "normal": [0, 0, -1], "radius": 2 },
+//         { "type": "triangle", "vertices": [[0, 0, 5], [1, 0, 5], [0, 1, 5]] },
+//         { "type": "mesh", "file": "models/bunny.obj", "smooth": true }
+//     ]
+// }
+//
+// Lights are point lights unless their type is "directional", which shines like the sun in
+// the given direction, or "spot", which shines into a cone with the given angles in degrees
+// around its direction. Lights are white and equally bright at any distance unless given a
+// color and a falloff of "none", "linear" or "inverse_square". The intensity of point and
+// spot lights is in "W/sr" by default, or the power in "W" emitted in all directions.
+//
+// The area lights "rect", "disk" and "sphere" cast soft shadows and are visible to the
+// camera. Rectangles are spanned by the edges u and v around their center. Rectangles and
+// disks only shine to the side of their normal, which is u x v for rectangles, and their
+// intensity is the one along it. Every shading point sends "samples" shadow rays towards an
+// area light, 16 by default.
+//
+// The background is a color like [127, 127, 127], which is the default, a "gradient" or an
+// "environment" map. Environment maps are equirectangular .hdr or .pfm images given as
+// { "type": "environment", "file": "sky.hdr", "rotation": 90, "intensity": 1, "samples": 16 },
+// where the center of the image lies along +z and the rotation in degrees turns it around the
+// vertical axis. They also light the scene, with "samples" shadow rays per shading point.
+//
+// Objects either reference a material by name, define one inline as an object, or just give
+// a color. Mesh files are resolved relative to the scene file.
+//
+// Materials with an emission glow in that color, which may exceed 255 for bright lamps.
+// Emissive spheres, triangles and meshes also light the scene like area lights, with an
+// inverse square falloff. Emissive planes and disks only glow.
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(String, io::Error),
+    Json(String, JsonError),
+    Obj(ObjError),
+    Image(ImageError),
+    Invalid {
+        file: String,
+        line: usize,
+        key: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(file, err) => write!(f, "{}: {}", file, err),
+            SceneError::Json(file, err) => write!(f, "{}:{}", file, err),
+            SceneError::Obj(err) => write!(f, "{}", err),
+            SceneError::Image(err) => write!(f, "{}", err),
+            SceneError::Invalid {
+                file,
+                line,
+                key,
+                message,
+            } => write!(f, "{}:{}: {}: {}", file, line, key, message),
+        }
+    }
+}
+
+impl Error for SceneError {}
+
+pub struct SceneDescription {
+    pub scene: Scene,
+    pub camera: Camera,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub fn load_scene(path: &Path) -> Result<SceneDescription, SceneError> {
+    let file_name = path.display().to_string();
+    let source = fs::read_to_string(path).map_err(|err| SceneError::Io(file_name.clone(), err))?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    parse_scene(&source, &file_name, directory)
+}
+
+pub fn parse_scene(
+    source: &str,
+    file: &str,
+    directory: &Path,
+) -> Result<SceneDescription, SceneError> {
+    let document = json::parse(source).map_err(|err| SceneError::Json(file.to_string(), err))?;
+    let root = Field {
+        json: &document,
+        key: String::new(),
+        file,
+    };
+    root.check_keys(&[
+        "resolution",
+        "camera",
+        "ambient_light",
+        "background",
+        "lights",
+        "materials",
+        "objects",
+    ])?;
+
+    let (width, height) = match root.get("resolution") {
+        Some(resolution) => {
+            resolution.check_keys(&["width", "height"])?;
+            (
+                resolution.require("width")?.dimension()?,
+                resolution.require("height")?.dimension()?,
+            )
+        }
+        None => (512, 512),
+    };
+
+    let camera = match root.get("camera") {
+        Some(camera) => {
+            camera.check_keys(&["position", "look_at", "up", "roll", "fov", "fov_axis"])?;
+            let position = match camera.get("position") {
+                Some(position) => position.vector()?,
+                None => Vector3::zero(),
+            };
+            let fov = match camera.get("fov") {
+                Some(fov) => fov.number_in(0.0, 180.0)?,
+                None => 75.0,
+            };
+            let mut result = match camera.get("look_at") {
+                Some(field) => {
+                    let target = field.vector()?;
+                    if target == position {
+                        return Err(field.error("the camera can not look at its own position"));
+                    }
+                    let up = match camera.get("up") {
+                        Some(up) => up.direction()?,
+                        None => Vector3::new(0.0, 1.0, 0.0),
+                    };
+                    Camera::look_at(position, &target, &up, width as f64, height as f64, fov)
+                }
+                None => {
+                    if let Some(up) = camera.get("up") {
+                        return Err(up.error("an up vector requires look_at"));
+                    }
+                    Camera::new(position, width as f64, height as f64, fov)
+                }
+            };
+            if let Some(roll) = camera.get("roll") {
+                result.roll(roll.number()?);
+            }
+            if let Some(fov_axis) = camera.get("fov_axis") {
+                result.fov_axis = match fov_axis.string()? {
+                    "horizontal" => FovAxis::Horizontal,
+                    "vertical" => FovAxis::Vertical,
+                    _ => return Err(fov_axis.error("expected \"horizontal\" or \"vertical\"")),
+                };
+            }
+            result
+        }
+        None => Camera::new_at_zero(width as f64, height as f64, 75.0),
+    };
+
+    let ambient_light = match root.get("ambient_light") {
+        Some(ambient_light) => ambient_light.non_negative_number()?,
+        None => 0.1,
+    };
+
+    let mut scene = Scene::new(Vec::new(), ambient_light);
+    if let Some(background) = root.get("background") {
+        scene.background = parse_background(&background, directory)?;
+    }
+    if let Some(entries) = root.get("lights") {
+        for light in entries.array()? {
+            add_light(&mut scene, &light)?;
+        }
+    }
+
+    let mut materials = HashMap::new();
+    if let Some(entries) = root.get("materials") {
+        for (name, material) in entries.members()? {
+            materials.insert(name, parse_material(&material)?);
+        }
+    }
+
+    if let Some(objects) = root.get("objects") {
+        for object in objects.array()? {
+            add_object(&mut scene, &object, &materials, directory)?;
+        }
+    }
+
+    Ok(SceneDescription {
+        scene,
+        camera,
+        width,
+        height,
+    })
+}
+
+fn parse_background(field: &Field, directory: &Path) -> Result<Background, SceneError> {
+    // A plain color needs no type
+    if let JsonValue::Array(_) = field.json.value {
+        return Ok(Background::Color(field.vector()?));
+    }
+    let kind = field.require("type")?;
+    match kind.string()? {
+        "color" => {
+            field.check_keys(&["type", "color"])?;
+            Ok(Background::Color(field.require("color")?.vector()?))
+        }
+        "gradient" => {
+            field.check_keys(&["type", "bottom", "top"])?;
+            Ok(Background::Gradient {
+                bottom: field.require("bottom")?.vector()?,
+                top: field.require("top")?.vector()?,
+            })
+        }
+        "environment" => {
+            field.check_keys(&["type", "file", "rotation", "intensity", "samples"])?;
+            let file = field.require("file")?;
+            let path = directory.join(file.string()?).display().to_string();
+            let image = match Path::new(&path).extension().and_then(|ext| ext.to_str()) {
+                Some(ext) if ext.eq_ignore_ascii_case("hdr") => hdr::read_file(&path),
+                Some(ext) if ext.eq_ignore_ascii_case("pfm") => pfm::read_file(&path),
+                _ => return Err(file.error("expected a .hdr or .pfm file")),
+            }
+            .map_err(SceneError::Image)?;
+            if image.width() == 0 || image.height() == 0 {
+                return Err(file.error("the environment map is empty"));
+            }
+            let rotation = match field.get("rotation") {
+                Some(rotation) => rotation.number()?,
+                None => 0.0,
+            };
+            let mut map = EnvironmentMap::new(image, rotation);
+            if let Some(intensity) = field.get("intensity") {
+                map.intensity = intensity.non_negative_number()?;
+            }
+            if let Some(samples) = field.get("samples") {
+                map.samples = samples.dimension()?;
+            }
+            Ok(Background::Environment(map))
+        }
+        other => Err(kind.error(&format!("unknown background type '{}'", other))),
+    }
+}
+
+fn add_light(scene: &mut Scene, light: &Field) -> Result<(), SceneError> {
+    // Lights without a type are point lights
+    let kind = light.get("type");
+    let kind_name = match kind {
+        Some(ref kind) => kind.string()?,
+        None => "point",
+    };
+    match kind_name {
+        "point" => {
+            light.check_keys(&["type", "position", "intensity", "color", "falloff", "unit"])?;
+            scene.add_light(Light {
+                color: light_color(light)?,
+                falloff: light_falloff(light)?,
+                unit: light_unit(light)?,
+                ..Light::new(
+                    light.require("intensity")?.non_negative_number()?,
+                    light.require("position")?.vector()?,
+                )
+            });
+        }
+        "directional" => {
+            light.check_keys(&["type", "direction", "intensity", "color"])?;
+            scene.add_light(DirectionalLight {
+                color: light_color(light)?,
+                ..DirectionalLight::new(
+                    light.require("intensity")?.non_negative_number()?,
+                    light.require("direction")?.direction()?,
+                )
+            });
+        }
+        "spot" => {
+            light.check_keys(&[
+                "type",
+                "position",
+                "direction",
+                "inner_angle",
+                "outer_angle",
+                "intensity",
+                "color",
+                "falloff",
+                "unit",
+            ])?;
+            let inner_angle = light.require("inner_angle")?.number_between(0.0, 180.0)?;
+            let field = light.require("outer_angle")?;
+            let outer_angle = field.number_between(0.0, 180.0)?;
+            if outer_angle < inner_angle {
+                return Err(field.error("the outer angle must not be smaller than the inner angle"));
+            }
+            scene.add_light(SpotLight {
+                color: light_color(light)?,
+                falloff: light_falloff(light)?,
+                unit: light_unit(light)?,
+                ..SpotLight::new(
+                    light.require("intensity")?.non_negative_number()?,
+                    light.require("position")?.vector()?,
+                    light.require("direction")?.direction()?,
+                    inner_angle,
+                    outer_angle,
+                )
+            });
+        }
+        "rect" => {
+            light.check_keys(&[
+                "type",
+                "center",
+                "u",
+                "v",
+                "intensity",
+                "color",
+                "falloff",
+                "unit",
+                "samples",
+            ])?;
+            let u = light.require("u")?.direction()?;
+            let field = light.require("v")?;
+            let v = field.direction()?;
+            if u.cross(&v).len() == 0.0 {
+                return Err(field.error("the edges u and v must not be parallel"));
+            }
+            scene.add_light(RectLight {
+                color: light_color(light)?,
+                falloff: light_falloff(light)?,
+                unit: light_unit(light)?,
+                samples: light_samples(light)?,
+                ..RectLight::new(
+                    light.require("intensity")?.non_negative_number()?,
+                    light.require("center")?.vector()?,
+                    u,
+                    v,
+                )
+            });
+        }
+        "disk" => {
+            light.check_keys(&[
+                "type",
+                "center",
+                "normal",
+                "radius",
+                "intensity",
+                "color",
+                "falloff",
+                "unit",
+                "samples",
+            ])?;
+            scene.add_light(DiskLight {
+                color: light_color(light)?,
+                falloff: light_falloff(light)?,
+                unit: light_unit(light)?,
+                samples: light_samples(light)?,
+                ..DiskLight::new(
+                    light.require("intensity")?.non_negative_number()?,
+                    light.require("center")?.vector()?,
+                    light.require("normal")?.direction()?,
+                    light.require("radius")?.positive_number()?,
+                )
+            });
+        }
+        "sphere" => {
+            light.check_keys(&[
+                "type",
+                "center",
+                "radius",
+                "intensity",
+                "color",
+                "falloff",
+                "unit",
+                "samples",
+            ])?;
+            scene.add_light(SphereLight {
+                color: light_color(light)?,
+                falloff: light_falloff(light)?,
+                unit: light_unit(light)?,
+                samples: light_samples(light)?,
+                ..SphereLight::new(
+                    light.require("intensity")?.non_negative_number()?,
+                    light.require("center")?.vector()?,
+                    light.require("radius")?.positive_number()?,
+                )
+            });
+        }
+        other => {
+            let kind = kind.as_ref().expect("Only a given type can be unknown");
+            return Err(kind.error(&format!("unknown light type '{}'", other)));
+        }
+    }
+    Ok(())
+}
+
+fn light_color(light: &Field) -> Result<Vector3, SceneError> {
+    match light.get("color") {
+        Some(color) => color.vector(),
+        None => Ok(Vector3::new(255.0, 255.0, 255.0)),
+    }
+}
+
+fn light_falloff(light: &Field) -> Result<Falloff, SceneError> {
+    match light.get("falloff") {
+        Some(falloff) => match falloff.string()? {
+            "none" => Ok(Falloff::None),
+            "linear" => Ok(Falloff::Linear),
+            "inverse_square" => Ok(Falloff::InverseSquare),
+            _ => Err(falloff.error("expected \"none\", \"linear\" or \"inverse_square\"")),
+        },
+        None => Ok(Falloff::None),
+    }
+}
+
+fn light_unit(light: &Field) -> Result<LightUnit, SceneError> {
+    match light.get("unit") {
+        Some(unit) => match unit.string()? {
+            "W/sr" => Ok(LightUnit::WattsPerSteradian),
+            "W" => Ok(LightUnit::Watts),
+            _ => Err(unit.error("expected \"W/sr\" or \"W\"")),
+        },
+        None => Ok(LightUnit::WattsPerSteradian),
+    }
+}
+
+fn light_samples(light: &Field) -> Result<u32, SceneError> {
+    match light.get("samples") {
+        Some(samples) => samples.dimension(),
+        None => Ok(16),
+    }
+}
+
+fn add_object(
+    scene: &mut Scene,
+    object: &Field,
+    materials: &HashMap<String, Material>,
+    directory: &Path,
+) -> Result<(), SceneError> {
+    let kind = object.require("type")?;
+    let material = match (object.get("material"), object.get("color")) {
+        (Some(_), Some(color)) => {
+            return Err(color.error("an object can have either a material or a color"))
+        }
+        (Some(material), None) => match material.json.value {
+            JsonValue::Object(_) => Some(parse_material(&material)?),
+            _ => {
+                let name = material.string()?;
+                match materials.get(name) {
+                    Some(material) => Some(material.clone()),
+                    None => return Err(material.error(&format!("unknown material '{}'", name))),
+                }
+            }
+        },
+        (None, Some(color)) => Some(Material::new(color.vector()?)),
+        (None, None) => None,
+    };
+
+    match kind.string()? {
+        "sphere" => {
+            object.check_keys(&["type", "material", "color", "center", "radius"])?;
+            scene.add_shape(Sphere::new(
+                object.require("center")?.vector()?,
+                object.require("radius")?.positive_number()?,
+                material.clone().unwrap_or_default(),
+            ));
+        }
+        "plane" => {
+            object.check_keys(&["type", "material", "color", "point", "normal"])?;
+            scene.add_shape(Plane::new(
+                object.require("point")?.vector()?,
+                object.require("normal")?.direction()?,
+                material.clone().unwrap_or_default(),
+            ));
+        }
+        "disk" => {
+            object.check_keys(&["type", "material", "color", "center", "normal", "radius"])?;
+            scene.add_shape(Disk::new(
+                object.require("center")?.vector()?,
+                object.require("normal")?.direction()?,
+                object.require("radius")?.positive_number()?,
+                material.clone().unwrap_or_default(),
+            ));
+        }
+        "triangle" => {
+            object.check_keys(&["type", "material", "color", "vertices"])?;
+            let field = object.require("vertices")?;
+            let vertices = field.array()?;
+            if vertices.len() != 3 {
+                return Err(field.error("a triangle needs exactly 3 vertices"));
+            }
+            scene.add_shape(Triangle::new(
+                vertices[0].vector()?,
+                vertices[1].vector()?,
+                vertices[2].vector()?,
+                material.clone().unwrap_or_default(),
+            ));
+        }
+        "mesh" => {
+            object.check_keys(&["type", "material", "color", "file", "smooth"])?;
+            let path = directory.join(object.require("file")?.string()?);
+            let model = obj::load_obj(&path).map_err(SceneError::Obj)?;
+            let smooth = match object.get("smooth") {
+                Some(smooth) => Some(smooth.boolean()?),
+                None => None,
+            };
+            for mut mesh in model.into_meshes() {
+                // The scene's material overrides the one of the OBJ file
+                if let Some(ref material) = material {
+                    mesh.material = material.clone();
+                }
+                if let Some(smooth) = smooth {
+                    if smooth && mesh.normals.is_empty() {
+                        mesh.compute_vertex_normals();
+                    }
+                    mesh.smooth = smooth;
+                }
+                scene.add_mesh(mesh);
+            }
+        }
+        other => return Err(kind.error(&format!("unknown object type '{}'", other))),
+    }
+    Ok(())
+}
+
+fn parse_material(field: &Field) -> Result<Material, SceneError> {
+    field.check_keys(&[
+        "color",
+        "diffuse",
+        "specular",
+        "specular_color",
+        "shininess",
+        "ambient",
+        "reflectivity",
+        "transparency",
+        "index_of_refraction",
+        "absorption",
+        "emission",
+    ])?;
+    let mut material = Material::new(field.require("color")?.vector()?);
+    if let Some(diffuse) = field.get("diffuse") {
+        material.diffuse = diffuse.non_negative_number()?;
+    }
+    if let Some(specular) = field.get("specular") {
+        material.specular = specular.non_negative_number()?;
+    }
+    if let Some(specular_color) = field.get("specular_color") {
+        material.specular_color = specular_color.vector()?;
+    }
+    if let Some(shininess) = field.get("shininess") {
+        material.shininess = shininess.exponent()?;
+    }
+    if let Some(ambient) = field.get("ambient") {
+        material.ambient = ambient.non_negative_number()?;
+    }
+    if let Some(reflectivity) = field.get("reflectivity") {
+        material.reflectivity = reflectivity.number_between(0.0, 1.0)?;
+    }
+    if let Some(transparency) = field.get("transparency") {
+        material.transparency = transparency.number_between(0.0, 1.0)?;
+    }
+    if let Some(index_of_refraction) = field.get("index_of_refraction") {
+        material.index_of_refraction = index_of_refraction.positive_number()?;
+    }
+    if let Some(absorption) = field.get("absorption") {
+        material.absorption = absorption.vector()?;
+    }
+    if let Some(emission) = field.get("emission") {
+        material.emission = emission.vector()?;
+    }
+    Ok(material)
+}
+
+// A value in the scene file together with the path of keys leading to it, for error messages
+struct Field<'a> {
+    json: &'a Json,
+    key: String,
+    file: &'a str,
+}
+
+impl<'a> Field<'a> {
+    fn error(&self, message: &str) -> SceneError {
+        SceneError::Invalid {
+            file: self.file.to_string(),
+            line: self.json.line,
+            key: if self.key.is_empty() {
+                "scene".to_string()
+            } else {
+                self.key.clone()
+            },
+            message: message.to_string(),
+        }
+    }
+
+    fn type_error(&self, expected: &str) -> SceneError {
+        self.error(&format!(
+            "expected {}, found {}",
+            expected,
+            self.json.type_name()
+        ))
+    }
+
+    fn child(&self, key: String, json: &'a Json) -> Field<'a> {
+        Field {
+            json,
+            key,
+            file: self.file,
+        }
+    }
+
+    fn members(&self) -> Result<Vec<(String, Field<'a>)>, SceneError> {
+        match self.json.value {
+            JsonValue::Object(ref members) => Ok(members
+                .iter()
+                .map(|(key, json)| (key.clone(), self.child(self.child_key(key), json)))
+                .collect()),
+            _ => Err(self.type_error("an object")),
+        }
+    }
+
+    fn child_key(&self, key: &str) -> String {
+        if self.key.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", self.key, key)
+        }
+    }
+
+    // Checks that the field is an object and that it only contains the given keys,
+    // so that typos don't go unnoticed
+    fn check_keys(&self, allowed: &[&str]) -> Result<(), SceneError> {
+        for (key, member) in self.members()? {
+            if !allowed.contains(&key.as_str()) {
+                return Err(member.error("unknown key"));
+            }
+        }
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Option<Field<'a>> {
+        self.json
+            .get(key)
+            .map(|json| self.child(self.child_key(key), json))
+    }
+
+    fn require(&self, key: &str) -> Result<Field<'a>, SceneError> {
+        match self.get(key) {
+            Some(field) => Ok(field),
+            None => Err(self
+                .child(self.child_key(key), self.json)
+                .error("missing key")),
+        }
+    }
+
+    fn array(&self) -> Result<Vec<Field<'a>>, SceneError> {
+        match self.json.value {
+            JsonValue::Array(ref elements) => Ok(elements
+                .iter()
+                .enumerate()
+                .map(|(i, json)| self.child(format!("{}[{}]", self.key, i), json))
+                .collect()),
+            _ => Err(self.type_error("an array")),
+        }
+    }
+
+    fn string(&self) -> Result<&'a str, SceneError> {
+        match self.json.value {
+            JsonValue::String(ref string) => Ok(string),
+            _ => Err(self.type_error("a string")),
+        }
+    }
+
+    fn boolean(&self) -> Result<bool, SceneError> {
+        match self.json.value {
+            JsonValue::Bool(value) => Ok(value),
+            _ => Err(self.type_error("a boolean")),
+        }
+    }
+
+    fn number(&self) -> Result<f64, SceneError> {
+        match self.json.value {
+            JsonValue::Number(number) => Ok(number),
+            _ => Err(self.type_error("a number")),
+        }
+    }
+
+    fn positive_number(&self) -> Result<f64, SceneError> {
+        let number = self.number()?;
+        if number > 0.0 {
+            Ok(number)
+        } else {
+            Err(self.error("expected a positive number"))
+        }
+    }
+
+    fn non_negative_number(&self) -> Result<f64, SceneError> {
+        let number = self.number()?;
+        if number >= 0.0 {
+            Ok(number)
+        } else {
+            Err(self.error("expected a non-negative number"))
+        }
+    }
+
+    fn number_between(&self, min: f64, max: f64) -> Result<f64, SceneError> {
+        // Like number_in, but including the bounds
+        let number = self.number()?;
+        if number >= min && number <= max {
+            Ok(number)
+        } else {
+            Err(self.error(&format!("expected a number from {} to {}", min, max)))
+        }
+    }
+
+    fn number_in(&self, min: f64, max: f64) -> Result<f64, SceneError> {
+        let number = self.number()?;
+        if number > min && number < max {
+            Ok(number)
+        } else {
+            Err(self.error(&format!("expected a number between {} and {}", min, max)))
+        }
+    }
+
+    fn dimension(&self) -> Result<u32, SceneError> {
+        let number = self.number()?;
+        if number >= 1.0 && number.fract() == 0.0 && number <= u32::MAX as f64 {
+            Ok(number as u32)
+        } else {
+            Err(self.error("expected a positive integer"))
+        }
+    }
+
+    fn exponent(&self) -> Result<i32, SceneError> {
+        // A positive integer that fits the exponent of powi
+        let number = self.dimension()?;
+        if number <= i32::MAX as u32 {
+            Ok(number as i32)
+        } else {
+            Err(self.error(&format!("expected a positive integer up to {}", i32::MAX)))
+        }
+    }
+
+    fn vector(&self) -> Result<Vector3, SceneError> {
+        let elements = self.array().map_err(|_| self.type_error("[x, y, z]"))?;
+        if elements.len() != 3 {
+            return Err(self.error("expected [x, y, z]"));
+        }
+        Ok(Vector3::new(
+            elements[0].number()?,
+            elements[1].number()?,
+            elements[2].number()?,
+        ))
+    }
+
+    fn direction(&self) -> Result<Vector3, SceneError> {
+        let vector = self.vector()?;
+        if vector.len() > 0.0 {
+            Ok(vector)
+        } else {
+            Err(self.error("a direction must not be the zero vector"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use math::Rng;
+    use raytracing::Ray;
+    use std::env;
+    use std::f64::consts::PI;
+    use std::process;
+    use util::framebuffer::{Framebuffer, LinearRGB};
+
+    fn parse(source: &str) -> Result<SceneDescription, SceneError> {
+        parse_scene(source, "test.json", Path::new(""))
+    }
+
+    fn error_location(source: &str) -> (usize, String) {
+        match parse(source) {
+            Err(SceneError::Invalid { line, key, .. }) => (line, key),
+            Err(err) => panic!("unexpected error {}", err),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn test_parse_scene() {
+        let source = r#"{
+            "resolution": { "width": 320, "height": 200 },
+            "camera": { "position": [0, 0, -5], "fov": 90 },
+            "ambient_light": 0.2,
+            "lights": [ { "position": [0, -5, 4], "intensity": 1.2 } ],
+            "materials": { "red": { "color": [255, 0, 0], "specular": 0, "shininess": 40 } },
+            "objects": [
+                { "type": "sphere", "center": [0, 0, 5], "radius": 1.5, "material": "red" },
+                { "type": "plane", "point": [0, 3, 0], "normal": [0, -1, 0],
+                  "material": { "color": [0, 255, 0], "diffuse": 1.2, "reflectivity": 0.5 } },
+                { "type": "disk", "center": [0, 0, 9], "normal": [0, 0, -1], "radius": 2,
+                  "material": { "color": [0, 0, 0], "transparency": 1, "index_of_refraction": 1.33,
+                                "absorption": [0.5, 0.1, 0] } },
+                { "type": "triangle", "vertices": [[0, 0, 5], [1, 0, 5], [0, 1, 5]] }
+            ]
+        }"#;
+        let description = parse(source).unwrap();
+        assert_eq!((description.width, description.height), (320, 200));
+        assert_eq!(description.scene.ambient_light, 0.2);
+        assert_eq!(description.scene.lights.len(), 1);
+        assert_eq!(description.scene.shapes.len(), 4);
+        let red = description.scene.shapes[0].material();
+        assert_eq!(red.color, Vector3::red());
+        assert_eq!(red.specular, 0.0);
+        assert_eq!(red.shininess, 40);
+        assert_eq!(description.scene.shapes[1].material().diffuse, 1.2);
+        assert_eq!(description.scene.shapes[1].material().reflectivity, 0.5);
+        let water = description.scene.shapes[2].material();
+        assert_eq!(water.transparency, 1.0);
+        assert_eq!(water.index_of_refraction, 1.33);
+        assert_eq!(water.absorption, Vector3::new(0.5, 0.1, 0.0));
+        assert_eq!(*description.scene.shapes[3].material(), Material::default());
+    }
+
+    #[test]
+    fn test_empty_scene_uses_defaults() {
+        let description = parse("{}").unwrap();
+        assert_eq!((description.width, description.height), (512, 512));
+        assert!(description.scene.shapes.is_empty());
+    }
+
+    #[test]
+    fn test_look_at_camera() {
+        let source = r#"{
+            "resolution": { "width": 16, "height": 16 },
+            "camera": {
+                "position": [0, 5, 0], "look_at": [0, 0, 0], "up": [0, 0, 1], "roll": 90,
+                "fov_axis": "vertical"
+            }
+        }"#;
+        let camera = parse(source).unwrap().camera;
+        assert_eq!(camera.fov_axis, FovAxis::Vertical);
+        let center = camera.get_camera_ray(8.0, 8.0).direction;
+        assert!((&center - &Vector3::new(0.0, -1.0, 0.0)).len() < 1e-12);
+
+        let source = "{\n\"camera\": {\n  \"position\": [1, 2, 3],\n  \"look_at\": [1, 2, 3]\n}\n}";
+        assert_eq!(error_location(source), (4, "camera.look_at".to_string()));
+        let source = "{\"camera\": {\"up\": [0, 1, 0]}}";
+        assert_eq!(error_location(source), (1, "camera.up".to_string()));
+        let source = "{\"camera\": {\"fov_axis\": \"diagonal\"}}";
+        assert_eq!(error_location(source), (1, "camera.fov_axis".to_string()));
+    }
+
+    #[test]
+    fn test_light_color_falloff_and_unit() {
+        let source = r#"{
+            "lights": [
+                { "position": [0, 5, 0], "intensity": 1.5 },
+                { "position": [1, 2, 3], "intensity": 100, "color": [255, 128, 0],
+                  "falloff": "inverse_square", "unit": "W" }
+            ]
+        }"#;
+        let lights = parse(source).unwrap().scene.lights;
+        let sample = lights[0].illuminate(&Vector3::zero(), &mut Rng::new(0))[0].clone();
+        assert_eq!(sample.intensity, Vector3::new(1.5, 1.5, 1.5));
+        assert_eq!(sample.distance, 5.0);
+
+        // 100 W spread over the sphere of directions, two units away
+        let sample =
+            lights[1].illuminate(&Vector3::new(1.0, 2.0, 1.0), &mut Rng::new(0))[0].clone();
+        let expected = 100.0 / (4.0 * PI) / 4.0;
+        assert!((sample.intensity.x - expected).abs() < 1e-12);
+        assert!((sample.intensity.y - expected * 128.0 / 255.0).abs() < 1e-12);
+        assert_eq!(sample.intensity.z, 0.0);
+
+        let source =
+            "{\"lights\": [{\"position\": [0, 0, 0], \"intensity\": 1,\n\"falloff\": \"cubic\"}]}";
+        assert_eq!(error_location(source), (2, "lights[0].falloff".to_string()));
+    }
+
+    #[test]
+    fn test_directional_and_spot_lights() {
+        let source = r#"{
+            "lights": [
+                { "type": "directional", "direction": [0, -3, 0], "intensity": 0.5,
+                  "color": [0, 255, 0] },
+                { "type": "spot", "position": [0, 5, 0], "direction": [0, -1, 0],
+                  "inner_angle": 10, "outer_angle": 20, "intensity": 2 }
+            ]
+        }"#;
+        let lights = parse(source).unwrap().scene.lights;
+        let sun = lights[0].illuminate(&Vector3::new(7.0, 0.0, 7.0), &mut Rng::new(0))[0].clone();
+        assert_eq!(sun.direction, Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(sun.intensity, Vector3::new(0.0, 0.5, 0.0));
+        let spot = &lights[1];
+        assert_eq!(
+            spot.illuminate(&Vector3::zero(), &mut Rng::new(0))[0].intensity,
+            Vector3::new(2.0, 2.0, 2.0)
+        );
+        assert!(spot
+            .illuminate(&Vector3::new(5.0, 0.0, 0.0), &mut Rng::new(0))
+            .is_empty());
+
+        let source = "{\"lights\": [{\"type\": \"spot\", \"position\": [0, 0, 0],\n\"direction\": [0, 0, 1], \"intensity\": 1, \"inner_angle\": 30,\n\"outer_angle\": 20}]}";
+        assert_eq!(
+            error_location(source),
+            (3, "lights[0].outer_angle".to_string())
+        );
+        let source = "{\"lights\": [{\"type\": \"laser\"}]}";
+        assert_eq!(error_location(source), (1, "lights[0].type".to_string()));
+    }
+
+    #[test]
+    fn test_backgrounds() {
+        let direction = Vector3::new(0.0, 1.0, 0.0);
+        let scene = parse(r#"{ "background": [10, 20, 30] }"#).unwrap().scene;
+        assert_eq!(
+            scene.background.color(&direction),
+            Vector3::new(10.0, 20.0, 30.0)
+        );
+        let source = r#"{
+            "background": { "type": "gradient", "bottom": [0, 0, 0], "top": [0, 0, 200] }
+        }"#;
+        let scene = parse(source).unwrap().scene;
+        assert_eq!(
+            scene.background.color(&direction),
+            Vector3::new(0.0, 0.0, 200.0)
+        );
+
+        // Environment maps are loaded relative to the scene file
+        let directory = env::temp_dir().join(format!("rusttracer-scene-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let image = Framebuffer::filled(4, 2, LinearRGB::new(2.0, 1.0, 0.5));
+        pfm::write_file(&image, &directory.join("sky.pfm").display().to_string()).unwrap();
+        let source = r#"{
+            "background": { "type": "environment", "file": "sky.pfm", "rotation": 45,
+                            "intensity": 0.5, "samples": 4 }
+        }"#;
+        let scene = parse_scene(source, "test.json", &directory).unwrap().scene;
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(
+            scene.background.color(&direction),
+            Vector3::new(255.0, 127.5, 63.75)
+        );
+        let light = scene.background.light().unwrap();
+        assert_eq!(
+            light.illuminate(&Vector3::zero(), &mut Rng::new(0)).len(),
+            4
+        );
+
+        let source = r#"{ "background": { "type": "environment", "file": "sky.png" } }"#;
+        assert_eq!(error_location(source), (1, "background.file".to_string()));
+        match parse(r#"{ "background": { "type": "environment", "file": "missing.hdr" } }"#) {
+            Err(SceneError::Image(ImageError::Io(..))) => (),
+            _ => panic!("expected an I/O error"),
+        }
+        let source = r#"{ "background": { "type": "starfield" } }"#;
+        assert_eq!(error_location(source), (1, "background.type".to_string()));
+    }
+
+    #[test]
+    fn test_emissive_objects_become_lights() {
+        let source = r#"{
+            "materials": { "lamp": { "color": [0, 0, 0], "emission": [1000, 800, 600] } },
+            "objects": [
+                { "type": "sphere", "center": [0, 4, 0], "radius": 0.5, "material": "lamp" },
+                { "type": "plane", "point": [0, 0, 0], "normal": [0, 1, 0], "material": "lamp" },
+                { "type": "sphere", "center": [0, 0, 5], "radius": 1, "color": [255, 0, 0] }
+            ]
+        }"#;
+        let scene = parse(source).unwrap().scene;
+        assert_eq!(
+            scene.shapes[0].material().emission,
+            Vector3::new(1000.0, 800.0, 600.0)
+        );
+        // The plane glows, but only the sphere can be sampled
+        assert_eq!(scene.emitters.len(), 1);
+        assert!(scene.lights.is_empty());
+    }
+
+    #[test]
+    fn test_area_lights() {
+        let source = r#"{
+            "lights": [
+                { "type": "rect", "center": [0, 4, 0], "u": [1, 0, 0], "v": [0, 0, 1],
+                  "intensity": 2, "samples": 4 },
+                { "type": "disk", "center": [0, 4, 0], "normal": [0, -1, 0], "radius": 0.5,
+                  "intensity": 2, "color": [255, 0, 0] },
+                { "type": "sphere", "center": [0, 4, 0], "radius": 0.5, "intensity": 2,
+                  "samples": 9 }
+            ]
+        }"#;
+        let lights = parse(source).unwrap().scene.lights;
+        let mut rng = Rng::new(0);
+        assert_eq!(lights[0].illuminate(&Vector3::zero(), &mut rng).len(), 4);
+        let disk = lights[1].illuminate(&Vector3::zero(), &mut rng);
+        assert_eq!(disk.len(), 16);
+        assert!(disk.iter().all(|sample| sample.intensity.y == 0.0));
+        assert!(lights[2].illuminate(&Vector3::zero(), &mut rng).len() <= 9);
+        // The camera sees the rectangle
+        let ray = Ray::new(Vector3::zero(), Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(lights[0].intersect(&ray).map(|(t, _)| t), Some(4.0));
+
+        let source = "{\"lights\": [{\"type\": \"rect\", \"center\": [0, 0, 0],\n\"u\": [1, 0, 0], \"v\": [2, 0, 0], \"intensity\": 1}]}";
+        assert_eq!(error_location(source), (2, "lights[0].v".to_string()));
+        let source = "{\"lights\": [{\"type\": \"sphere\", \"center\": [0, 0, 0],\n\"radius\": 1, \"intensity\": 1, \"samples\": 0}]}";
+        assert_eq!(error_location(source), (2, "lights[0].samples".to_string()));
+    }
+
+    #[test]
+    fn test_error_points_to_key_and_line() {
+        let source = "{\n\"objects\": [\n  { \"type\": \"sphere\", \"center\": [0, 0, 5], \"radius\": 1 },\n  { \"type\": \"sphere\",\n    \"center\": [0, 0, 5], \"radius\": \"big\" }\n]\n}";
+        assert_eq!(error_location(source), (5, "objects[1].radius".to_string()));
+    }
+
+    #[test]
+    fn test_error_on_missing_key() {
+        let source = "{\n\"lights\": [\n  { \"position\": [0, 0, 0] }\n]\n}";
+        assert_eq!(
+            error_location(source),
+            (3, "lights[0].intensity".to_string())
+        );
+    }
+
+    #[test]
+    fn test_error_on_negative_light() {
+        let source = "{\n\"ambient_light\": -0.1\n}";
+        assert_eq!(error_location(source), (2, "ambient_light".to_string()));
+
+        let source = "{\"lights\": [{\"type\": \"directional\", \"direction\": [0, -1, 0],\n\"intensity\": -1}]}";
+        assert_eq!(
+            error_location(source),
+            (2, "lights[0].intensity".to_string())
+        );
+    }
+
+    #[test]
+    fn test_error_on_unknown_key_and_material() {
+        let source = "{\n\"camera\": {\n  \"fvo\": 90\n}\n}";
+        assert_eq!(error_location(source), (3, "camera.fvo".to_string()));
+
+        let source = "{\"objects\": [{\"type\": \"sphere\", \"center\": [0, 0, 0], \"radius\": 1, \"material\": \"gold\"}]}";
+        assert_eq!(
+            error_location(source),
+            (1, "objects[0].material".to_string())
+        );
+    }
+
+    #[test]
+    fn test_error_on_shininess_out_of_range() {
+        let source =
+            "{\"materials\": {\"shiny\": {\"color\": [0, 0, 0], \"shininess\": 2147483648}}}";
+        assert_eq!(
+            error_location(source),
+            (1, "materials.shiny.shininess".to_string())
+        );
+        let source =
+            "{\"materials\": {\"shiny\": {\"color\": [0, 0, 0], \"shininess\": 2147483647}}}";
+        assert!(parse(source).is_ok());
+    }
+
+    #[test]
+    fn test_syntax_error_has_line() {
+        match parse("{\n\"ambient_light\": 0.1,\n}") {
+            Err(SceneError::Json(_, err)) => assert_eq!(err.line, 3),
+            _ => panic!("expected a syntax error"),
+        }
+    }
+}
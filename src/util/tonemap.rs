@@ -0,0 +1,246 @@
+use std::fmt;
+use std::str::FromStr;
+use util::framebuffer::{Framebuffer, LinearRGB};
+
+// Turns the linear radiance of a rendered image into display values in [0, 1], which can then
+// be quantized for 8 bit formats like PNG. The radiance is first scaled by the exposure, then
+// compressed into [0, 1] by the operator and finally encoded with the sRGB transfer function.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    // Cuts off everything brighter than 1
+    Clamp,
+    // L / (1 + L) applied to the luminance, never quite reaches white
+    Reinhard,
+    // Reinhard with a white point, the luminance that is mapped to 1.
+    // Without one the brightest pixel in the image is used.
+    ExtendedReinhard(Option<f32>),
+    // Krzysztof Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+impl FromStr for Operator {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Operator, String> {
+        match name {
+            "clamp" => Ok(Operator::Clamp),
+            "reinhard" => Ok(Operator::Reinhard),
+            "extended-reinhard" => Ok(Operator::ExtendedReinhard(None)),
+            "aces" => Ok(Operator::Aces),
+            _ => Err(format!(
+                "unknown tone mapping operator {}, expected clamp, reinhard, \
+                 extended-reinhard or aces",
+                name
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Operator::Clamp => "clamp",
+            Operator::Reinhard => "reinhard",
+            Operator::ExtendedReinhard(_) => "extended-reinhard",
+            Operator::Aces => "aces",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapping {
+    pub operator: Operator,
+    // In stops, every stop doubles the brightness
+    pub exposure: f32,
+    pub srgb: bool,
+}
+
+impl Default for ToneMapping {
+    fn default() -> ToneMapping {
+        ToneMapping {
+            operator: Operator::Clamp,
+            exposure: 0.0,
+            srgb: true,
+        }
+    }
+}
+
+impl ToneMapping {
+    pub fn new(operator: Operator, exposure: f32, srgb: bool) -> ToneMapping {
+        ToneMapping {
+            operator,
+            exposure,
+            srgb,
+        }
+    }
+
+    pub fn apply(&self, framebuffer: &Framebuffer) -> Framebuffer {
+        let scale = self.exposure.exp2();
+        let operator = match self.operator {
+            Operator::ExtendedReinhard(None) => {
+                let white = framebuffer
+                    .pixels()
+                    .iter()
+                    .map(|&pixel| luminance(pixel) * scale)
+                    .fold(0.0, f32::max);
+                // An image that is all black has no white point, any positive value will do
+                Operator::ExtendedReinhard(Some(if white > 0.0 { white } else { 1.0 }))
+            }
+            operator => operator,
+        };
+
+        let mut result = framebuffer.clone();
+        for pixel in result.pixels_mut() {
+            let exposed = LinearRGB::new(pixel.r * scale, pixel.g * scale, pixel.b * scale);
+            let mapped = map(operator, exposed);
+            *pixel = if self.srgb {
+                LinearRGB::new(
+                    srgb_encode(mapped.r),
+                    srgb_encode(mapped.g),
+                    srgb_encode(mapped.b),
+                )
+            } else {
+                mapped
+            };
+        }
+        result
+    }
+}
+
+fn map(operator: Operator, color: LinearRGB) -> LinearRGB {
+    match operator {
+        Operator::Clamp => LinearRGB::new(
+            color.r.clamp(0.0, 1.0),
+            color.g.clamp(0.0, 1.0),
+            color.b.clamp(0.0, 1.0),
+        ),
+        Operator::Reinhard => scale_luminance(color, |l| l / (1.0 + l)),
+        Operator::ExtendedReinhard(white) => {
+            let white = white.unwrap_or(1.0);
+            scale_luminance(color, |l| l * (1.0 + l / (white * white)) / (1.0 + l))
+        }
+        Operator::Aces => LinearRGB::new(aces(color.r), aces(color.g), aces(color.b)),
+    }
+}
+
+fn luminance(color: LinearRGB) -> f32 {
+    0.2126 * color.r + 0.7152 * color.g + 0.0722 * color.b
+}
+
+fn scale_luminance<F: Fn(f32) -> f32>(color: LinearRGB, curve: F) -> LinearRGB {
+    // Compresses the luminance and scales all channels alike, which keeps the hue.
+    // Saturated colors can still leave [0, 1] in single channels, so we clamp afterwards.
+    let l = luminance(color);
+    if l <= 0.0 {
+        return LinearRGB::black();
+    }
+    let scale = curve(l) / l;
+    LinearRGB::new(
+        (color.r * scale).clamp(0.0, 1.0),
+        (color.g * scale).clamp(0.0, 1.0),
+        (color.b * scale).clamp(0.0, 1.0),
+    )
+}
+
+fn aces(x: f32) -> f32 {
+    let x = x.max(0.0);
+    (x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+}
+
+pub fn srgb_encode(value: f32) -> f32 {
+    // The sRGB transfer function, linear near black and a gamma of about 2.2 elsewhere
+    if value <= 0.003_130_8 {
+        12.92 * value
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn single(color: LinearRGB) -> Framebuffer {
+        Framebuffer::filled(1, 1, color)
+    }
+
+    fn gray(value: f32) -> LinearRGB {
+        LinearRGB::new(value, value, value)
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_srgb_encode() {
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert_close(srgb_encode(1.0), 1.0);
+        assert_close(srgb_encode(0.002), 0.02584);
+        // Linear mid gray is a lot brighter once encoded
+        assert_close(srgb_encode(0.5), 0.735_357);
+    }
+
+    #[test]
+    fn test_exposure_doubles_per_stop() {
+        let tone_mapping = ToneMapping::new(Operator::Clamp, 2.0, false);
+        let result = tone_mapping.apply(&single(gray(0.1)));
+        assert_close(result.pixels()[0].r, 0.4);
+        let result = tone_mapping.apply(&single(gray(0.3)));
+        assert_eq!(result.pixels()[0], gray(1.0));
+    }
+
+    #[test]
+    fn test_reinhard() {
+        let tone_mapping = ToneMapping::new(Operator::Reinhard, 0.0, false);
+        let result = tone_mapping.apply(&single(gray(1.0)));
+        assert_close(result.pixels()[0].g, 0.5);
+        let result = tone_mapping.apply(&single(gray(1000.0)));
+        assert!(result.pixels()[0].g < 1.0);
+        // The hue is preserved
+        let result = tone_mapping.apply(&single(LinearRGB::new(0.8, 0.4, 0.2)));
+        let pixel = result.pixels()[0];
+        assert_close(pixel.r / pixel.g, 2.0);
+        assert_close(pixel.g / pixel.b, 2.0);
+    }
+
+    #[test]
+    fn test_extended_reinhard_maps_white_point_to_one() {
+        let tone_mapping = ToneMapping::new(Operator::ExtendedReinhard(Some(4.0)), 0.0, false);
+        let result = tone_mapping.apply(&single(gray(4.0)));
+        assert_close(result.pixels()[0].r, 1.0);
+
+        // Without a white point the brightest pixel becomes white
+        let mut framebuffer = Framebuffer::new(2, 1);
+        framebuffer.set_pixel(0, 0, gray(8.0));
+        framebuffer.set_pixel(1, 0, gray(1.0));
+        let tone_mapping = ToneMapping::new(Operator::ExtendedReinhard(None), 0.0, false);
+        let result = tone_mapping.apply(&framebuffer);
+        assert_close(result.pixels()[0].r, 1.0);
+        assert_close(result.pixels()[1].r, (1.0 + 1.0 / 64.0) / 2.0);
+    }
+
+    #[test]
+    fn test_aces_is_monotonic_and_bounded() {
+        let tone_mapping = ToneMapping::new(Operator::Aces, 0.0, true);
+        let mut previous = -1.0;
+        for i in 0..100 {
+            let value = tone_mapping.apply(&single(gray(i as f32 * 0.25))).pixels()[0].r;
+            assert!(value >= previous && value <= 1.0);
+            previous = value;
+        }
+        assert_eq!(tone_mapping.apply(&single(gray(0.0))).pixels()[0].r, 0.0);
+        assert!(tone_mapping.apply(&single(gray(0.5))).pixels()[0].r < previous);
+    }
+
+    #[test]
+    fn test_parse_operator() {
+        for name in &["clamp", "reinhard", "extended-reinhard", "aces"] {
+            let operator: Operator = name.parse().unwrap();
+            assert_eq!(operator.to_string(), *name);
+        }
+        assert!("filmic".parse::<Operator>().is_err());
+    }
+}